@@ -0,0 +1,48 @@
+// Embedding burlap in a Rust program
+// Run with `cargo run --example embed`
+use std::cell::Cell;
+use std::rc::Rc;
+
+use burlap::{Interpreter, Value};
+
+fn main() {
+    let mut sack = Interpreter::new();
+    // Functions and globals stay around between evals
+    sack.eval("functi greet(name) { return \"Hello, \" + name + \"!\"; }")
+        .expect("valid code");
+    sack.set_global("player", Value::str("Bob"));
+    let greeting = sack.eval("greet(player)").expect("valid code");
+    println!("{}", greeting.to_string().unwrap());
+
+    // Calling sack functions from Rust
+    sack.eval("let score = 0; functi addScore(n) { score += n; return score; }")
+        .expect("valid code");
+    sack.call("addScore", vec![Value::Int(10)]).expect("addScore works");
+    println!("score: {:?}", sack.get_global("score"));
+
+    // Rust functions, args are converted (and checked) for you
+    sack.register_fn("clamp", |n: i32, lo: i32, hi: i32| n.clamp(lo, hi));
+    sack.register_fn("shout", |s: String| s.to_uppercase() + "!");
+    // They can use the host's state
+    let coins = Rc::new(Cell::new(0));
+    let wallet = coins.clone();
+    sack.register_fn("addCoins", move |n: i32| wallet.set(wallet.get() + n));
+    // Or take any values, `None` means any number of args
+    sack.register("count", None, |args| Ok(Value::Int(args.len() as i64)));
+    let val = sack.eval("addCoins(clamp(50, 0, 10)); shout(\"hi\")")
+        .expect("valid code");
+    println!("{}, coins: {}", val.to_string().unwrap(), coins.get());
+    println!("{:?}", sack.eval("count(1, 2, 3)"));
+    // Bad args are errors, like builtins
+    let err = sack.eval("clamp(\"five\", 0, 10)").unwrap_err();
+    println!("{:?} error: {}", err.kind, err);
+
+    // Errors are returned, not printed
+    let err = sack.eval("let x = ;").unwrap_err();
+    println!("{:?} error: {}", err.kind, err);
+    let err = sack.eval("functi bad() { return [][1]; }\nbad()").unwrap_err();
+    println!("{:?} error: {}", err.kind, err);
+    for frame in err.traceback {
+        println!("  in {} at {}:{}", frame.name, frame.file, frame.line);
+    }
+}
@@ -0,0 +1,74 @@
+/* The burlap C API, for hosting sack from C/C++
+ * Link with `-lburlap`, `cargo build` makes target/debug/libburlap.so
+ */
+#ifndef BURLAP_H
+#define BURLAP_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct burlap burlap;
+
+/* Value types */
+#define BURLAP_NONE 0
+#define BURLAP_INT 1
+#define BURLAP_FLOAT 2
+#define BURLAP_BOOL 3
+#define BURLAP_BYTE 4
+#define BURLAP_STR 5
+/* Lists, files, functions, and numbers too big for `i` (only returned),
+   `str` is how they print */
+#define BURLAP_OTHER 6
+
+typedef struct {
+    int type;
+    union {
+        int64_t i;
+        double f;
+        bool b;
+        uint8_t byte;
+        const char *str;
+    } as;
+} burlap_value;
+
+/* A C function for sack, returns false on error
+ * On error `ret` can be a BURLAP_STR with the message
+ * Strings in `args` only live until the callback returns
+ */
+typedef bool (*burlap_callback)(
+    const burlap_value *args, size_t argc, burlap_value *ret, void *data
+);
+
+/* Makes an interpreter, free it with `burlap_free` */
+burlap *burlap_new(void);
+void burlap_free(burlap *sack);
+
+/* These return false on error, see `burlap_last_error`
+ * `out` can be NULL, strings in it live until the next call
+ */
+bool burlap_eval(burlap *sack, const char *src, burlap_value *out);
+bool burlap_eval_file(burlap *sack, const char *path, burlap_value *out);
+bool burlap_call(
+    burlap *sack, const char *name,
+    const burlap_value *args, size_t argc, burlap_value *out
+);
+
+/* The last error, or "" if there hasn't been one */
+const char *burlap_last_error(const burlap *sack);
+
+/* Adds a C function, `arg_num` is -1 for any number of args */
+bool burlap_register(
+    burlap *sack, const char *name, int arg_num,
+    burlap_callback callback, void *data
+);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,334 @@
+use std::fs;
+
+use num_bigint::BigInt;
+
+use crate::common::Span;
+use crate::compiler::Program;
+use crate::value::{Value, View};
+use crate::vm::Opcode;
+
+// Precompiled programs (.skc files)
+// Layout (all numbers are little endian):
+//  magic, version (u16)
+//  ops (u32 len, bytes)
+//  consts (u32 len, [u8 tag, value])
+//  functis (u32 len, [name, u32 pos, i32 arg#])
+//  line table (u32 len, [u32 start, u32 end, u32 line])
+//  file table (u32 len, [u32 start, u32 end, name])
+//  span table (u32 len, [u32 start, u32 end, u32 line, u32 at, u32 size])
+// Strings are a u32 len and then UTF-8
+
+pub const MAGIC: &[u8; 4] = b"\x7fSKC";
+// Bump whenever the layout or the instruction set changes
+pub const VERSION: u16 = 11;
+
+// Const tags
+const TAG_NONE: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_FLOAT: u8 = 2;
+const TAG_BOOL: u8 = 3;
+const TAG_BYTE: u8 = 4;
+const TAG_STR: u8 = 5;
+const TAG_BIG: u8 = 6;
+
+// Writing
+fn write_u32(out: &mut Vec<u8>, val: u32) {
+    out.extend(val.to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, val: &str) {
+    write_u32(out, val.len() as u32);
+    out.extend(val.as_bytes());
+}
+
+fn write_value(out: &mut Vec<u8>, val: &Value) -> Result<(), String> {
+    match val.view() {
+        View::None => out.push(TAG_NONE),
+        View::Int(i) => {
+            out.push(TAG_INT);
+            out.extend(i.to_le_bytes());
+        },
+        View::Float(f) => {
+            out.push(TAG_FLOAT);
+            out.extend(f.to_le_bytes());
+        },
+        View::Bool(b) => {
+            out.push(TAG_BOOL);
+            out.push(b as u8);
+        },
+        View::Byte(b) => {
+            out.push(TAG_BYTE);
+            out.push(b);
+        },
+        View::Str(s) => {
+            out.push(TAG_STR);
+            write_str(out, s);
+        },
+        View::BigInt(i) => {
+            out.push(TAG_BIG);
+            let bytes = i.to_signed_bytes_le();
+            write_u32(out, bytes.len() as u32);
+            out.extend(bytes);
+        },
+        _ => return Err(format!("cannot save constant {:?}", val)),
+    }
+    return Ok(());
+}
+
+pub fn save(program: &Program) -> Result<Vec<u8>, String> {
+    let mut out: Vec<u8> = MAGIC.to_vec();
+    out.extend(VERSION.to_le_bytes());
+    // Ops
+    write_u32(&mut out, program.ops.len() as u32);
+    out.extend(&program.ops);
+    // Consts
+    write_u32(&mut out, program.consts.len() as u32);
+    for val in &program.consts {
+        write_value(&mut out, val)?;
+    }
+    // Functis, sorted so the output is the same every time
+    let mut functis: Vec<_> = program.functis.iter().collect();
+    functis.sort();
+    write_u32(&mut out, functis.len() as u32);
+    for (name, (pos, arg_num)) in functis {
+        write_str(&mut out, name);
+        write_u32(&mut out, *pos as u32);
+        out.extend(arg_num.to_le_bytes());
+    }
+    // Side tables
+    write_u32(&mut out, program.line_table.len() as u32);
+    for (start, end, line) in &program.line_table {
+        write_u32(&mut out, *start);
+        write_u32(&mut out, *end);
+        write_u32(&mut out, *line);
+    }
+    write_u32(&mut out, program.file_table.len() as u32);
+    for (start, end, file) in &program.file_table {
+        write_u32(&mut out, *start);
+        write_u32(&mut out, *end);
+        write_str(&mut out, file);
+    }
+    write_u32(&mut out, program.span_table.len() as u32);
+    for (start, end, span) in &program.span_table {
+        write_u32(&mut out, *start);
+        write_u32(&mut out, *end);
+        write_u32(&mut out, span.line);
+        write_u32(&mut out, span.at);
+        write_u32(&mut out, span.size);
+    }
+    return Ok(out);
+}
+
+// Reading
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, size: usize) -> Result<&[u8], String> {
+        if self.bytes.len() - self.at < size {
+            return Err("unexpected end of file".to_string());
+        }
+        self.at += size;
+        return Ok(&self.bytes[self.at - size..self.at]);
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        return Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        return Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        return String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "invalid UTF-8 in string".to_string());
+    }
+
+    // Reads a table length, each item is at least `min_size` bytes
+    fn len(&mut self, min_size: usize) -> Result<usize, String> {
+        let len = self.u32()? as usize;
+        if len.saturating_mul(min_size) > self.bytes.len() - self.at {
+            return Err("unexpected end of file".to_string());
+        }
+        return Ok(len);
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        return Ok(match self.u8()? {
+            TAG_NONE => Value::None,
+            TAG_INT => Value::Int(self.i64()?),
+            TAG_FLOAT => Value::Float(
+                f64::from_le_bytes(self.take(8)?.try_into().unwrap())
+            ),
+            TAG_BOOL => Value::Bool(self.u8()? != 0),
+            TAG_BYTE => Value::Byte(self.u8()?),
+            TAG_STR => Value::str(self.str()?),
+            TAG_BIG => {
+                let len = self.u32()? as usize;
+                Value::from_big(BigInt::from_signed_bytes_le(self.take(len)?))
+            },
+            tag => return Err(format!("invalid constant tag {}", tag)),
+        });
+    }
+}
+
+// Turns a byte into an opcode, None if it isn't one
+pub fn decode(op: u8) -> Option<Opcode> {
+    if op > Opcode::ENDTRY as u8 {
+        return None;
+    }
+    return Some(unsafe { std::mem::transmute::<u8, Opcode>(op) });
+}
+
+// Gets the number of operand bytes after an opcode
+pub fn operand_size(op: Opcode) -> usize {
+    return match op {
+        Opcode::PUSH | Opcode::PLV | Opcode::SLV | Opcode::SKLV
+            | Opcode::ALV => 1,
+        Opcode::PUSH3 | Opcode::TCO | Opcode::TRY | Opcode::CALLD
+            | Opcode::JMPU | Opcode::JMPB | Opcode::JMPNT => 3,
+        _ => 0,
+    };
+}
+
+// Reads the operand of the instruction at `at`
+pub fn read_operand(ops: &[u8], at: usize, size: usize) -> usize {
+    return ops[at + 1..at + 1 + size].iter()
+        .fold(0, |ret, x| (ret << 8) + *x as usize);
+}
+
+// Checks that the ops can't read or jump out of bounds
+fn check_ops(program: &Program) -> Result<(), String> {
+    let ops = &program.ops;
+    // Find where every instruction starts
+    let mut starts = vec![false; ops.len() + 1];
+    let mut at = 0;
+    while at < ops.len() {
+        let Some(op) = decode(ops[at]) else {
+            return Err(format!("invalid opcode {} at {}", ops[at], at));
+        };
+        let size = operand_size(op);
+        if at + size >= ops.len() {
+            return Err(format!("truncated instruction at {}", at));
+        }
+        starts[at] = true;
+        at += size + 1;
+    }
+    // Check operands
+    let read = |at: usize, size: usize| read_operand(ops, at, size);
+    for at in (0..ops.len()).filter(|x| starts[*x]) {
+        let op = decode(ops[at]).unwrap();
+        let target = match op {
+            Opcode::PUSH | Opcode::PUSH3 => {
+                let index = read(at, operand_size(op));
+                if index >= program.consts.len() {
+                    return Err(format!(
+                        "constant {} at {} is out of bounds", index, at
+                    ));
+                }
+                continue;
+            },
+            // Functions start after the JMPU that skips over them
+            Opcode::LFN => {
+                if ops.get(at + 1) != Some(&(Opcode::JMPU as u8)) {
+                    return Err(format!("function at {} has no body", at));
+                }
+                continue;
+            },
+            // Direct calls go to the start of a function
+            Opcode::CALLD => {
+                let pos = read(at, 3);
+                if !program.functis.values().any(|(start, _)| *start == pos) {
+                    return Err(format!("call at {} has an invalid target", at));
+                }
+                continue;
+            },
+            // Jumps are relative to the end of the instruction
+            Opcode::JMPU | Opcode::JMPNT | Opcode::TRY =>
+                (at + 3).checked_add(read(at, 3)),
+            Opcode::JMPB | Opcode::TCO => (at + 3).checked_sub(read(at, 3)),
+            _ => continue,
+        };
+        if !target.is_some_and(|x| x < ops.len() && starts[x]) {
+            return Err(format!("jump at {} has an invalid target", at));
+        }
+    }
+    // Functis
+    for (name, (pos, _)) in &program.functis {
+        if *pos >= ops.len() || !starts[*pos] {
+            return Err(format!("function {} has an invalid address", name));
+        }
+    }
+    return Ok(());
+}
+
+pub fn load(bytes: &[u8]) -> Result<Program, String> {
+    let mut reader = Reader{bytes, at: 0};
+    // Header
+    if bytes.len() < MAGIC.len() || reader.take(MAGIC.len())? != MAGIC {
+        return Err("not a compiled burlap file".to_string());
+    }
+    let version = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(format!(
+            "compiled with bytecode version {}, but this is version {}",
+            version, VERSION
+        ));
+    }
+    let mut program = Program::new();
+    // Ops
+    let len = reader.len(1)?;
+    program.ops = reader.take(len)?.to_vec();
+    // Consts
+    for _ in 0..reader.len(1)? {
+        let val = reader.value()?;
+        program.consts.push(val);
+    }
+    // Functis
+    for _ in 0..reader.len(12)? {
+        let name = reader.str()?;
+        let pos = reader.u32()? as usize;
+        let arg_num = reader.i32()?;
+        program.functis.insert(name, (pos, arg_num));
+    }
+    // Side tables
+    for _ in 0..reader.len(12)? {
+        let entry = (reader.u32()?, reader.u32()?, reader.u32()?);
+        program.line_table.push(entry);
+    }
+    for _ in 0..reader.len(12)? {
+        let entry = (reader.u32()?, reader.u32()?, reader.str()?);
+        program.file_table.push(entry);
+    }
+    for _ in 0..reader.len(20)? {
+        let (start, end) = (reader.u32()?, reader.u32()?);
+        let span = Span{
+            line: reader.u32()?, at: reader.u32()?, size: reader.u32()?
+        };
+        program.span_table.push((start, end, span));
+    }
+    if reader.at != bytes.len() {
+        return Err("trailing data after the end".to_string());
+    }
+    check_ops(&program)?;
+    return Ok(program);
+}
+
+// Reads and checks a .skc file
+pub fn load_file(name: &str) -> Result<Program, String> {
+    let bytes = fs::read(name)
+        .map_err(|err| format!("failed to open file: {}", err))?;
+    return load(&bytes).map_err(|err| format!("{}: {}", name, err));
+}
@@ -0,0 +1,219 @@
+// The C API, for hosting burlap from C/C++ (see include/burlap.h)
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::value::{Value, View};
+
+// Value types, the same as `BURLAP_*` in burlap.h
+const BURLAP_NONE: c_int = 0;
+const BURLAP_INT: c_int = 1;
+const BURLAP_FLOAT: c_int = 2;
+const BURLAP_BOOL: c_int = 3;
+const BURLAP_BYTE: c_int = 4;
+const BURLAP_STR: c_int = 5;
+// Lists, files, functions, and too big numbers, `str` is how they print
+const BURLAP_OTHER: c_int = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union BurlapData {
+    int: i64,
+    float: f64,
+    boolean: bool,
+    byte: u8,
+    str: *const c_char,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BurlapValue {
+    kind: c_int,
+    data: BurlapData,
+}
+
+// A C callback, returns false on error (`ret` can be a string with why)
+type BurlapCallback = extern "C" fn(
+    *const BurlapValue, usize, *mut BurlapValue, *mut c_void
+) -> bool;
+
+pub struct Burlap {
+    sack: Interpreter,
+    error: CString,
+    // Strings given to C, they live until the next call
+    strings: Vec<CString>,
+}
+
+// Makes a C value, strings are kept in `strings`
+fn to_c_value(val: &Value, strings: &mut Vec<CString>) -> BurlapValue {
+    let (kind, data) = match val.view() {
+        View::None => (BURLAP_NONE, BurlapData { int: 0 }),
+        View::Int(i) => (BURLAP_INT, BurlapData { int: i }),
+        View::Float(f) => (BURLAP_FLOAT, BurlapData { float: f }),
+        View::Bool(b) => (BURLAP_BOOL, BurlapData { boolean: b }),
+        View::Byte(b) => (BURLAP_BYTE, BurlapData { byte: b }),
+        view => {
+            let kind = if let View::Str(_) = view {
+                BURLAP_STR
+            } else {
+                BURLAP_OTHER
+            };
+            // Strings can't have nulls in C
+            let s = val.to_string().unwrap_or_default().replace('\0', "");
+            let s = CString::new(s).expect("nulls were removed");
+            let data = BurlapData { str: s.as_ptr() };
+            strings.push(s);
+            (kind, data)
+        },
+    };
+    return BurlapValue { kind, data };
+}
+
+unsafe fn from_c_str(s: *const c_char) -> Result<String, String> {
+    if s.is_null() {
+        return Err("string is NULL".to_string());
+    }
+    return CStr::from_ptr(s).to_str().map(|s| s.to_string())
+        .map_err(|_| "string isn't valid UTF-8".to_string());
+}
+
+unsafe fn from_c_value(val: &BurlapValue) -> Result<Value, String> {
+    return Ok(match val.kind {
+        BURLAP_NONE => Value::None,
+        BURLAP_INT => Value::Int(val.data.int),
+        BURLAP_FLOAT => Value::decimal(val.data.float),
+        BURLAP_BOOL => Value::Bool(val.data.boolean),
+        BURLAP_BYTE => Value::Byte(val.data.byte),
+        BURLAP_STR => Value::str(from_c_str(val.data.str)?),
+        _ => return Err(format!("bad value type {}", val.kind)),
+    });
+}
+
+impl Burlap {
+    // Sets the error and returns false
+    fn fail(&mut self, msg: String) -> bool {
+        self.error = CString::new(msg.replace('\0', ""))
+            .expect("nulls were removed");
+        return false;
+    }
+
+    // Gives C the result
+    unsafe fn finish(
+        &mut self, result: Result<Value, String>, out: *mut BurlapValue
+    ) -> bool {
+        match result {
+            Ok(val) => {
+                if !out.is_null() {
+                    *out = to_c_value(&val, &mut self.strings);
+                }
+                return true;
+            },
+            Err(msg) => return self.fail(msg),
+        }
+    }
+}
+
+// Makes an interpreter, free it with `burlap_free`
+#[no_mangle]
+pub extern "C" fn burlap_new() -> *mut Burlap {
+    return Box::into_raw(Box::new(Burlap {
+        sack: Interpreter::new(), error: CString::default(), strings: vec![],
+    }));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn burlap_free(sack: *mut Burlap) {
+    if !sack.is_null() {
+        drop(Box::from_raw(sack));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn burlap_eval(
+    sack: *mut Burlap, src: *const c_char, out: *mut BurlapValue
+) -> bool {
+    let sack = &mut *sack;
+    sack.strings.clear();
+    let result = from_c_str(src).and_then(|src| {
+        sack.sack.eval(&src).map_err(|err| err.to_string())
+    });
+    return sack.finish(result, out);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn burlap_eval_file(
+    sack: *mut Burlap, path: *const c_char, out: *mut BurlapValue
+) -> bool {
+    let sack = &mut *sack;
+    sack.strings.clear();
+    let result = from_c_str(path).and_then(|path| {
+        sack.sack.eval_file(&path).map_err(|err| err.to_string())
+    });
+    return sack.finish(result, out);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn burlap_call(
+    sack: *mut Burlap, name: *const c_char,
+    args: *const BurlapValue, argc: usize, out: *mut BurlapValue
+) -> bool {
+    let sack = &mut *sack;
+    sack.strings.clear();
+    let name = match from_c_str(name) {
+        Ok(name) => name,
+        Err(msg) => return sack.fail(msg),
+    };
+    // Convert args
+    let mut values = Vec::with_capacity(argc);
+    for at in 0..argc {
+        match from_c_value(&*args.add(at)) {
+            Ok(val) => values.push(val),
+            Err(msg) => return sack.fail(msg),
+        }
+    }
+    let result = sack.sack.call(&name, values).map_err(|err| err.to_string());
+    return sack.finish(result, out);
+}
+
+// The last error, or "" if there hasn't been one
+#[no_mangle]
+pub unsafe extern "C" fn burlap_last_error(
+    sack: *const Burlap
+) -> *const c_char {
+    return (*sack).error.as_ptr();
+}
+
+// Adds a C function, `arg_num` is -1 for any number of args
+#[no_mangle]
+pub unsafe extern "C" fn burlap_register(
+    sack: *mut Burlap, name: *const c_char, arg_num: c_int,
+    callback: BurlapCallback, data: *mut c_void
+) -> bool {
+    let sack = &mut *sack;
+    let name = match from_c_str(name) {
+        Ok(name) => name,
+        Err(msg) => return sack.fail(msg),
+    };
+    let arg_num = usize::try_from(arg_num).ok();
+    let functi_name = name.clone();
+    sack.sack.register(&name, arg_num, move |args| {
+        let mut strings = vec![];
+        let c_args: Vec<BurlapValue> = args.iter()
+            .map(|arg| to_c_value(arg, &mut strings)).collect();
+        let mut ret = BurlapValue {
+            kind: BURLAP_NONE, data: BurlapData { str: ptr::null() }
+        };
+        let ok = callback(c_args.as_ptr(), c_args.len(), &mut ret, data);
+        // The callback's strings are copied before they can be freed
+        let ret = from_c_value(&ret);
+        if !ok {
+            return match ret.map(|val| val.into_rc::<String>()) {
+                Ok(Some(msg)) => Err(Rc::unwrap_or_clone(msg)),
+                _ => Err(format!("{}() failed", functi_name)),
+            };
+        }
+        return ret;
+    });
+    return true;
+}
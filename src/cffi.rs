@@ -4,7 +4,7 @@ use std::ffi::{CString, CStr};
 use libffi::middle::{Arg, Cif, CodePtr, Type};
 
 use crate::common::IMPOSSIBLE_STATE;
-use crate::value::Value;
+use crate::value::{Value, View};
 
 unsafe fn ptr_to_string(input: *mut libc::c_char) -> String {
     CStr::from_ptr(input).to_str().unwrap_or("").to_string()
@@ -35,15 +35,15 @@ pub fn load_functi(handle: usize, symname: String) -> Result<usize, String> {
 }
 
 fn get_val_c_type(val: &Value) -> Option<Type> {
-    Some(match val {
+    Some(match val.view() {
         // char* and void*
-        Value::Str(_) | Value::Ptr(_) => Type::pointer(),
-        // Int is i32
-        Value::Int(_) => Type::i32(),
-        // Float is i32
-        Value::Float(_) => Type::f32(),
+        View::Str(_) | View::Ptr(_) => Type::pointer(),
+        // Int is i64
+        View::Int(_) => Type::i64(),
+        // Float is f64
+        View::Float(_) => Type::f64(),
         // Bool and byte are u8
-        Value::Bool(_) | Value::Byte(_) => Type::u8(),
+        View::Bool(_) | View::Byte(_) => Type::u8(),
         // Anything else doesn't map
         _ => return None,
     })
@@ -53,10 +53,10 @@ fn get_str_c_type(val: String) -> Option<Type> {
     Some(match val.as_str() {
         // char* and void*
         "String" | "__burlap_ptr" => Type::pointer(),
-        // Int is i32
-        "Number" => Type::i32(),
-        // Float is f32
-        "Decimal" => Type::f32(),
+        // Int is i64
+        "Number" => Type::i64(),
+        // Float is f64
+        "Decimal" => Type::f64(),
         // Bool and byte are u8
         "Bool" | "Byte" => Type::u8(),
         // Void
@@ -66,21 +66,40 @@ fn get_str_c_type(val: String) -> Option<Type> {
     })
 }
 
-fn val_to_c(val: &Value) -> Result<Arg, CString> {
-    return Ok(match val {
+// An argument for C, the `Arg`s point into these so they must outlive the call
+enum CArg {
+    Str(CString),
+    Ptr(usize),
+    Int(i64),
+    Float(f64),
+    U8(u8),
+}
+
+fn val_to_c(val: &Value) -> CArg {
+    return match val.view() {
         // char* and void*
-        Value::Str(ref s) => return Err(CString::new(s.clone()).unwrap()),
-        Value::Ptr(ref p) => Arg::new(p),
-        // Int is i32
-        Value::Int(ref i) => Arg::new(i),
-        // Float is f32
-        Value::Float(ref f) => Arg::new(f),
+        View::Str(s) => CArg::Str(CString::new(s.as_str()).unwrap()),
+        View::Ptr(p) => CArg::Ptr(p),
+        // Int is i64
+        View::Int(i) => CArg::Int(i),
+        // Float is f64
+        View::Float(f) => CArg::Float(f),
         // Bool and byte are u8
-        Value::Bool(ref b) => Arg::new(b),
-        Value::Byte(ref b) => Arg::new(b),
+        View::Bool(b) => CArg::U8(b as u8),
+        View::Byte(b) => CArg::U8(b),
         // Anything else doesn't map
         _ => panic!("{}", IMPOSSIBLE_STATE),
-    });
+    };
+}
+
+fn c_arg(arg: &CArg) -> Arg {
+    return match arg {
+        CArg::Str(s) => Arg::new(s),
+        CArg::Ptr(p) => Arg::new(p),
+        CArg::Int(i) => Arg::new(i),
+        CArg::Float(f) => Arg::new(f),
+        CArg::U8(b) => Arg::new(b),
+    };
 }
 
 pub fn call(
@@ -88,8 +107,7 @@ pub fn call(
 ) -> Result<Value, String> {
     // Get the args
     let mut arg_types: Vec<Type> = Vec::with_capacity(args.len());
-    let mut c_args: Vec<Arg> = Vec::with_capacity(args.len());
-    let mut strings: Vec<CString> = vec![];
+    let mut c_vals: Vec<CArg> = Vec::with_capacity(args.len());
     for at in 0..args.len() {
         let arg = args.get(at).unwrap();
         // Get type
@@ -100,23 +118,18 @@ pub fn call(
         };
         arg_types.push(atype);
         // Convert arg to C equivalent
-        match val_to_c(arg) {
-            Ok(a) => c_args.push(a),
-            Err(cstr) => {
-                strings.push(cstr);
-                c_args.push(Arg::new(strings.last().unwrap()));
-            }
-        }
+        c_vals.push(val_to_c(arg));
     }
+    let c_args: Vec<Arg> = c_vals.iter().map(c_arg).collect();
     // Get the return type
     let Some(ret_t) = get_str_c_type(ret.clone()) else {
         return Err(format!("Invalid C type: {}", ret));
     };
     // Get signature
-    let cif = Cif::new(arg_types.into_iter(), ret_t);
+    let cif = Cif::new(arg_types, ret_t);
     // Call and return
     return Ok(unsafe { match ret.as_str() {
-        "String" => {Value::Str(
+        "String" => {Value::str(
             ptr_to_string(cif.call(CodePtr(ptr as *mut _), c_args.as_slice()))
         )},
         "__burlap_ptr" => {Value::Ptr(
@@ -125,7 +138,7 @@ pub fn call(
         "Number" => Value::Int(
             cif.call(CodePtr(ptr as *mut _), c_args.as_slice())
         ),
-        "Decimal" => Value::Float(
+        "Decimal" => Value::decimal(
             cif.call(CodePtr(ptr as *mut _), c_args.as_slice())
         ),
         "Bool" => Value::Bool(
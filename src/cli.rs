@@ -0,0 +1,347 @@
+// The burlap command
+use std::fs;
+use std::env;
+use std::path::PathBuf;
+use std::process::exit;
+
+use crate::{Arguments, to_ast};
+use crate::bytecode::{load_file, save};
+use crate::compiler::{compile, Program};
+use crate::common::{print_err, ErrType};
+use crate::debugger::debugger;
+use crate::disasm::disasm;
+use crate::formatter::{format, same_tokens};
+use crate::repl::repl;
+use crate::vm::{run, Vm};
+
+fn get_args() -> Result<Arguments, bool> {
+    let mut args = Arguments::new();
+    let mut file: String = "".to_string();
+    let mut cli_args = env::args()
+        .collect::<Vec<String>>().into_iter();
+    // Skip first arg
+    cli_args.next();
+    while let Some(arg) = cli_args.next() {
+        if !arg.starts_with('-') && file.is_empty() {
+            // Files
+            file = arg.to_string();
+            args.name = arg.to_string();
+            args.is_repl = false;
+            break;
+        } else if let Some(extension) = arg.strip_prefix("--use-") {
+            // Extensions
+            let extension = extension.to_string();
+            if extension == "all" {
+                // Push all extensions
+                args.extensions.push("auto-none".to_string());
+                args.extensions.push("burlap-extensions".to_string());
+            } else {
+                args.extensions.push(extension);
+            }
+        } else if arg == "--no-color" {
+            // Color is always the first argument
+            args.extensions.remove(0);
+        } else if arg == "-f" || arg == "--format" {
+            // Format
+            args.format = true;
+        } else if arg == "--check" {
+            // Check formatting
+            args.format = true;
+            args.check = true;
+        } else if arg == "--compile" {
+            // Compile to bytecode
+            args.compile = true;
+        } else if arg == "--disasm" {
+            // Print bytecode
+            args.disasm = true;
+        } else if arg == "-o" {
+            // Output file
+            let Some(output) = cli_args.next() else {
+                print_err(
+                    "'-o' is missing the output file", ErrType::Err,
+                    args.extensions.contains(&"color".to_string())
+                );
+                return Err(false);
+            };
+            args.output = output;
+        } else if arg == "-I" {
+            // Import directory
+            let Some(dir) = cli_args.next() else {
+                print_err(
+                    "'-I' is missing the directory", ErrType::Err,
+                    args.extensions.contains(&"color".to_string())
+                );
+                return Err(false);
+            };
+            args.import_dirs.push(PathBuf::from(dir));
+        } else if arg == "-d" || arg == "--debug" {
+            // Debug
+            args.is_debug = true;
+        } else if arg == "--debugger" {
+            // Interactive debugger
+            args.is_debugger = true;
+        } else if arg == "-h" || arg == "--help" {
+            // Print help
+            println!("Burlap v{}", env!("CARGO_PKG_VERSION"));
+            println!();
+            println!("Usage:");
+            println!("burlap <args> <file> <args for file>");
+            println!();
+            println!("Args:");
+            println!("\t-h --help\tprints help");
+            println!("\t--no-color\tdisables color");
+            println!("\t--use-X\tenables X feature");
+            println!("\t--use-all\tenables all features");
+            println!("\t- [command]\truns [command]");
+            println!("\t-f --format\tformat the file instead of running");
+            println!("\t--check\t\tfail if the file isn't formatted");
+            println!("\t--compile\tcompile the file to bytecode (.skc)");
+            println!("\t-o [file]\tthe output file for --compile");
+            println!("\t-I [dir]\tlooks for imports in [dir]");
+            println!("\t--disasm\tprint the bytecode instead of running");
+            println!("\t-d --debug\truns in debug mode");
+            println!("\t--debugger\truns in the interactive debugger");
+            println!();
+            println!(
+                "Thank you for using burlap! {}{}",
+                "If there are any issues please report them at ",
+                env!("CARGO_PKG_REPOSITORY")
+            );
+            return Err(true);
+        } else if arg == "-" && file.is_empty() {
+            // Read source from command line
+            args.is_repl = false;
+            args.name = "<cli>".to_string();
+            let Some(src) = cli_args.next() else {
+                print_err(
+                    "'-' is missing option source", ErrType::Err,
+                    args.extensions.contains(&"color".to_string())
+                );
+                return Err(false);
+            };
+            args.source = src.to_string();
+        } else if arg == "--" {
+            break;
+        } else {
+            // Anything else
+            print_err(
+                format!("unknown argument: {}", arg).as_str(), ErrType::Warn,
+                args.extensions.contains(&"color".to_string())
+            );
+        }
+    }
+    // The file isn't run when compiling, so `-o` can go after it
+    if args.compile {
+        while let Some(arg) = cli_args.next() {
+            if arg != "-o" {
+                print_err(
+                    format!("unknown argument: {}", arg).as_str(),
+                    ErrType::Warn,
+                    args.extensions.contains(&"color".to_string())
+                );
+                continue;
+            }
+            let Some(output) = cli_args.next() else {
+                print_err(
+                    "'-o' is missing the output file", ErrType::Err,
+                    args.extensions.contains(&"color".to_string())
+                );
+                return Err(false);
+            };
+            args.output = output;
+        }
+    }
+    // Get the args to the program
+    args.program_args.push(args.name.clone());
+    for arg in cli_args {
+        args.program_args.push(arg.to_string());
+    }
+    // Don't open files if source is filled, REPL, or bytecode
+    if args.is_repl || !args.source.is_empty() || is_compiled(&args) {
+        return Ok(args);
+    }
+    // Open file
+    match fs::read_to_string(file) {
+        Ok(v) => {
+            args.source = v;
+        },
+        Err(err) => {
+            // Report error
+            print_err(
+                format!("failed to open file: {}", err).as_str(), ErrType::Err,
+                args.extensions.contains(&"color".to_string())
+            );
+            return Err(false);
+        }
+    }
+    return Ok(args);
+}
+
+// Checks if the file is precompiled bytecode
+fn is_compiled(args: &Arguments) -> bool {
+    return !args.is_repl && args.name.ends_with(".skc");
+}
+
+// Writes the program to a .skc file
+fn save_file(args: &Arguments, program: &Program) -> bool {
+    let color = args.extensions.contains(&"color".to_string());
+    let output = if !args.output.is_empty() {
+        args.output.clone()
+    } else if args.name == "<cli>" {
+        "out.skc".to_string()
+    } else {
+        // foo.sk -> foo.skc
+        args.name.strip_suffix(".sk").unwrap_or(&args.name).to_string()
+            + ".skc"
+    };
+    let bytes = match save(program) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            print_err(err.as_str(), ErrType::Err, color);
+            return false;
+        }
+    };
+    if let Err(err) = fs::write(&output, bytes) {
+        print_err(
+            format!("failed to write file: {}", err).as_str(), ErrType::Err,
+            color
+        );
+        return false;
+    }
+    return true;
+}
+
+fn format_file(args: &Arguments) -> bool {
+    let color = args.extensions.contains(&"color".to_string());
+    let Some(formatted) = format(&args.source, args.name.clone(), color) else {
+        return false;
+    };
+    // The formatter must only change whitespace
+    if !same_tokens(&args.source, &formatted) {
+        print_err(
+            "formatting would change the meaning of the file", ErrType::Err,
+            color
+        );
+        return false;
+    }
+    // Check
+    if args.check {
+        if formatted != args.source {
+            print_err(
+                format!("{} is not formatted", args.name).as_str(),
+                ErrType::Err, color
+            );
+            return false;
+        }
+        return true;
+    }
+    // Code from argv is printed instead of written
+    if args.name == "<cli>" {
+        print!("{}", formatted);
+        return true;
+    }
+    if formatted == args.source {
+        return true;
+    }
+    if let Err(err) = fs::write(&args.name, formatted) {
+        print_err(
+            format!("failed to write file: {}", err).as_str(), ErrType::Err,
+            color
+        );
+        return false;
+    }
+    return true;
+}
+
+pub fn main() {
+    // Parse args
+    let mut args = match get_args() {
+        Ok(x) => x,
+        Err(failed) => exit(!failed as i32),
+    };
+    // Run
+    if args.is_repl {
+        if args.format || args.is_debugger || args.compile || args.disasm {
+            print_err(
+                if args.format {
+                    "formatting requires a file"
+                } else if args.is_debugger {
+                    "debugging requires a file"
+                } else if args.compile {
+                    "compiling requires a file"
+                } else {
+                    "disassembling requires a file"
+                }, ErrType::Err,
+                args.extensions.contains(&"color".to_string())
+            );
+            exit(1);
+        }
+        // Repl
+        repl(&mut args);
+    } else if is_compiled(&args) {
+        let color = args.extensions.contains(&"color".to_string());
+        if args.format || args.is_debugger || args.compile {
+            print_err(
+                "bytecode files can only be run", ErrType::Err, color
+            );
+            exit(1);
+        }
+        // Load bytecode
+        let mut vm = Vm::new(args.clone());
+        vm.program = match load_file(&args.name) {
+            Ok(program) => program,
+            Err(err) => {
+                print_err(err.as_str(), ErrType::Err, color);
+                exit(1);
+            }
+        };
+        if args.disasm {
+            print!("{}", disasm(&vm.program));
+            return;
+        }
+        // Run
+        if !run(&mut vm) {
+            exit(1);
+        }
+    } else if args.format {
+        // Format
+        if !format_file(&args) {
+            exit(1);
+        }
+    } else {
+        args.path = PathBuf::from(args.name.clone());
+        // Execute file
+        let Some(ast) = to_ast(&mut args) else {
+            exit(1);
+        };
+        if args.is_debugger {
+            // Debug
+            if !debugger(&mut args, ast) {
+                exit(1);
+            }
+            return;
+        }
+        let mut vm = Vm::new(args.clone());
+        // Fix import path
+        vm.program.path = args.path.clone();
+        vm.program.path.pop();
+        if !compile(ast, &mut args, &mut vm.program) {
+            exit(1);
+        }
+        if args.compile {
+            // Save instead of running
+            if !save_file(&args, &vm.program) {
+                exit(1);
+            }
+            return;
+        }
+        if args.disasm {
+            print!("{}", disasm(&vm.program));
+            return;
+        }
+        // Run
+        if !run(&mut vm) {
+            exit(1);
+        }
+    }
+}
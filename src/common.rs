@@ -1,8 +1,9 @@
+use std::cell::RefCell;
 use std::env;
 use std::fs;
 use std::io::{BufReader, BufRead};
 
-use crate::repl::get_repl_line;
+use crate::stdlib::get_std_source;
 
 // Stream
 #[derive(Debug, Clone)]
@@ -19,14 +20,75 @@ pub struct Stream {
     pub size: usize,
 }
 
+// Where an expression is, used for runtime errors
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: u32,
+    // Char pos in line
+    pub at: u32,
+    pub size: u32,
+}
+
 pub const IMPOSSIBLE_STATE: &str =
     "we've reached an impossible state, anything is possible, \
     the limits were in our heads all along, follow your dreams";
 
 // Errors
-pub enum ErrType{Err, Warn, Hint}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrType{Err, Warn, Hint, Note}
+
+// An error that was collected instead of printed (for embedding)
+pub struct Diagnostic {
+    pub errtype: ErrType,
+    pub msg: String,
+    // Where it is, `line` is 0 if it's unknown
+    pub file: String,
+    pub line: usize,
+    pub at: usize,
+}
+
+thread_local! {
+    // Collected errors, None when they're printed
+    static CAPTURED: RefCell<Option<Vec<Diagnostic>>> = const {
+        RefCell::new(None)
+    };
+    // The REPL's current source, so errors in it can show the line
+    static REPL_LINE: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+// Starts collecting errors instead of printing them
+pub fn capture_errs() {
+    CAPTURED.with(|c| *c.borrow_mut() = Some(vec![]));
+}
+
+// Stops collecting errors and returns them
+pub fn take_errs() -> Vec<Diagnostic> {
+    return CAPTURED.with(|c| c.borrow_mut().take().unwrap_or_default());
+}
+
+// Collects an error if errors are being collected
+fn capture(diagnostic: Diagnostic) -> bool {
+    return CAPTURED.with(|c| {
+        let mut captured = c.borrow_mut();
+        let Some(captured) = captured.as_mut() else {
+            return false;
+        };
+        captured.push(diagnostic);
+        return true;
+    });
+}
+
+pub fn set_repl_line(line: &str) {
+    REPL_LINE.with(|l| *l.borrow_mut() = line.to_string());
+}
+
 // Prints and error and returns the color
 pub fn print_err(msg: &str, errtype: ErrType, color: bool) -> String {
+    if capture(Diagnostic{
+        errtype, msg: msg.to_string(), file: "".to_string(), line: 0, at: 0
+    }) {
+        return "".to_string();
+    }
     // Get the name and color code from errtype
     let (color_code, name) = match errtype {
         // Red
@@ -35,9 +97,11 @@ pub fn print_err(msg: &str, errtype: ErrType, color: bool) -> String {
         ErrType::Warn => ("\x1b[1;33m", "warning"),
         // Cyan
         ErrType::Hint => ("\x1b[1;36m", "hint"),
+        // Blue
+        ErrType::Note => ("\x1b[1;34m", "note"),
     };
     if color {
-        println!("{}{}:\x1b[0m {}", color_code.clone(), name, msg);
+        println!("{}{}:\x1b[0m {}", color_code, name, msg);
         return color_code.to_string();
     } else {
         println!("{}: {}", name, msg);
@@ -45,31 +109,38 @@ pub fn print_err(msg: &str, errtype: ErrType, color: bool) -> String {
     }
 }
 
-fn get_line(stream: &Stream) -> String {
+// Gets the line a stream is on, None if it can't be read
+pub fn get_line(stream: &Stream) -> Option<String> {
+    if stream.line == 0 {
+        return None;
+    }
     // Special cases
     if stream.name == "<cli>" {
         let mut args = env::args();
         args.position(|x| x == "-");
-        return args.next()
-            .unwrap().lines().nth(stream.line - 1)
-            .expect("failed to read file for errors").to_string()
+        return args.next()?.lines().nth(stream.line - 1)
+            .map(|x| x.to_string());
     } else if stream.name == "<stdin>" {
-        return get_repl_line().clone().lines().nth(stream.line - 1)
-            .unwrap().to_string();
+        return REPL_LINE.with(|l| {
+            l.borrow().lines().nth(stream.line - 1).map(|x| x.to_string())
+        });
+    } else if let Some(src) = get_std_source(&stream.name) {
+        return src.lines().nth(stream.line - 1).map(|x| x.to_string());
     }
 
-    let name = stream.name.clone();
     // Open
-    let Ok(file) = fs::File::open(name) else {
-        panic!("Failed to open file for error printing!");
-    };
+    let file = fs::File::open(&stream.name).ok()?;
     // Read
-    return BufReader::new(file).lines().nth(stream.line - 1)
-        .expect("failed to read file for errors").unwrap()
+    return BufReader::new(file).lines().nth(stream.line - 1)?.ok();
 }
 
 pub fn err(stream: &Stream, msg: &str, errtype: ErrType, color: bool) {
-    let line = get_line(stream);
+    if capture(Diagnostic{
+        errtype, msg: msg.to_string(), file: stream.name.clone(),
+        line: stream.line, at: stream.at
+    }) {
+        return;
+    }
     // Print file name and line/char info ("test.sk:1:3: ")
     if color {
         print!("\x1b[1m{}:{}:{}:\x1b[0m ", stream.name, stream.line, stream.at);
@@ -78,19 +149,39 @@ pub fn err(stream: &Stream, msg: &str, errtype: ErrType, color: bool) {
     }
     // Print the type ("error:")
     let color_code = print_err(msg, errtype, color);
+    // The source might be gone (or the position unknown)
+    let Some(line) = get_line(stream) else {
+        return;
+    };
     // Print the line ("    1 | print("Hello World!");")
     let prefix = format!("    {} | ", stream.line);
     println!("{}{}", prefix, line.replace('\t', "    "));
     // Print arrow ("      |   ^")
     // Adjust for tabs
-    let at = line[0..stream.at].matches('\t').count()*3 + stream.at;
+    let at = line[0..stream.at.min(line.len())].matches('\t').count()*3
+        + stream.at;
     print!(
         "{}| {}",
         " ".repeat(prefix.len() - 2), " ".repeat(at)
     );
+    // Spans over multiple lines stop at the end of the first one
+    let size = stream.size.min(line.len().saturating_sub(stream.at).max(1));
     if color {
-        println!("{}{}\x1b[0m", color_code, "^".repeat(stream.size));
+        println!("{}{}\x1b[0m", color_code, "^".repeat(size));
     } else {
-        println!("{}", "^".repeat(stream.size));
+        println!("{}", "^".repeat(size));
+    }
+}
+
+// Makes a stream underlining all of a line, for errors without a column
+pub fn line_stream(name: &str, line: u32) -> Stream {
+    let mut ret = Stream{
+        name: name.to_string(), line: line as usize, at: 0, rat: 0, size: 0
+    };
+    if let Some(src) = get_line(&ret) {
+        let code = src.trim();
+        ret.at = src.len() - src.trim_start().len();
+        ret.size = code.chars().count();
     }
+    return ret;
 }
@@ -2,15 +2,28 @@ use std::cmp::Ordering;
 use std::path::PathBuf;
 
 use crate::Arguments;
-use crate::common::IMPOSSIBLE_STATE;
+use crate::common::{err, ErrType, Span, Stream, IMPOSSIBLE_STATE};
 use crate::lexer::TokenType;
 use crate::parser::{ASTNode, ASTNode::*};
 use crate::value::Value;
-use crate::vm::Opcode;
+use crate::vm::{args_err, Opcode};
 
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 
-#[derive(Debug)]
+// Something break/continue/return has to leave on the way out
+#[derive(Debug, Clone)]
+enum Exit {
+    // A body that might lower scope, and the NOPs to make RS if it does
+    Body(Vec<usize>),
+    // A scope that is always lowered
+    Scope,
+    // A try block
+    Try,
+    // A loop, (break jumps to fill, start)
+    Loop(Vec<usize>, usize),
+}
+
+#[derive(Debug, Clone)]
 pub struct Program {
     // Opcodes and constants
     pub ops: Vec<u8>,
@@ -21,8 +34,9 @@ pub struct Program {
     pub path: PathBuf,
 
     // Side tables
-    line_table: Vec<(u32, u32, u32)>,
-    file_table: Vec<(u32, u32, String)>,
+    pub line_table: Vec<(u32, u32, u32)>,
+    pub file_table: Vec<(u32, u32, String)>,
+    pub span_table: Vec<(u32, u32, Span)>,
 
     // Compiler info
     // The functi being compiled (name, args, address)
@@ -31,6 +45,21 @@ pub struct Program {
     needs_scope: bool,
     // Where in the byte code the current file started
     inc_start: u32,
+    // What the code being compiled is inside of (in the current function)
+    exits: Vec<Exit>,
+    // Locals in the current function by scope, their index is their slot
+    // It's empty at the top level, where variables are globals
+    locals: Vec<Vec<String>>,
+    // Globals declared so far (kept between REPL lines)
+    globals: FxHashSet<String>,
+    // Functions and globals in the code being compiled, they can be used
+    // before they're declared. (name : arg num) for functions
+    new_functis: FxHashMap<String, i32>,
+    new_globals: FxHashSet<String>,
+    // Direct calls to fill in at the end, (operand pos, name)
+    calls: Vec<(usize, String)>,
+    // Builtin functions, from the VM
+    natives: FxHashSet<String>,
 }
 
 impl Program {
@@ -40,13 +69,16 @@ impl Program {
             ops: vec![], consts: vec![],
             functis: FxHashMap::default(),
             path: PathBuf::from("."),
-            line_table: vec![], file_table: vec![],
-            needs_scope: false, inc_start: 0,
+            line_table: vec![], file_table: vec![], span_table: vec![],
+            needs_scope: false, inc_start: 0, exits: vec![],
             functi: ("".to_string(), 0, 0),
+            locals: vec![], globals: FxHashSet::default(),
+            new_functis: FxHashMap::default(), new_globals: FxHashSet::default(),
+            calls: vec![], natives: FxHashSet::default(),
         }
     }
 
-    fn bin_range<T: Clone>(index: u32, table: &Vec<(u32, u32, T)>) -> Option<T> {
+    fn bin_range<T: Clone>(index: u32, table: &[(u32, u32, T)]) -> Option<T> {
         table.binary_search_by(
             |x| {
                 if x.0 > index {
@@ -55,10 +87,25 @@ impl Program {
                     Ordering::Less
                 } else { Ordering::Equal }
             }
-        ).and_then(|x| Ok(table[x].2.clone())).ok()
+        ).map(|x| table[x].2.clone()).ok()
     }
 
-    pub fn get_info(&mut self, index: u32) -> (u32, String) {
+    // Marks the start of a new line in the line table
+    pub fn mark_line(&mut self, line: u32) {
+        let at = self.ops.len() as u32;
+        if let Some(last) = self.line_table.last_mut() {
+            if last.0 == at {
+                // Nothing was compiled for the last line
+                last.2 = line;
+                return;
+            }
+            last.1 = at - 1;
+        }
+        // The end is filled in by the next line
+        self.line_table.push((at, u32::MAX, line));
+    }
+
+    pub fn get_info(&self, index: u32) -> (u32, String) {
         let file = Self::bin_range(index, &self.file_table)
             .unwrap_or("Unknown File".to_string());
         let line = Self::bin_range(index, &self.line_table)
@@ -66,6 +113,78 @@ impl Program {
         (line, file)
     }
 
+    // Marks the ops from `start` to the end as coming from `span`
+    pub fn mark_span(&mut self, start: usize, span: &Span) {
+        if span.line != 0 && start < self.ops.len() {
+            self.span_table.push(
+                (start as u32, self.ops.len() as u32 - 1, *span)
+            );
+        }
+    }
+
+    // Gets the expression an instruction came from, if it's known
+    pub fn get_span(&self, index: u32) -> Option<Span> {
+        Self::bin_range(index, &self.span_table)
+    }
+
+    // Checks if an instruction is the first one of a statement
+    pub fn is_line_start(&self, index: u32) -> bool {
+        self.line_table.binary_search_by_key(&index, |x| x.0).is_ok()
+    }
+
+    // Finds the first instruction on a line (or the next line with code)
+    pub fn find_line(&self, file: &str, line: u32) -> Option<(usize, u32)> {
+        let mut ret: Option<(usize, u32)> = None;
+        for (start, _, l) in &self.line_table {
+            if *l < line || ret.is_some_and(|(_, best)| *l >= best) {
+                continue;
+            }
+            // Check the file, `test.sk` matches `tests/test.sk`
+            let Some(name) = Self::bin_range(*start, &self.file_table) else {
+                continue;
+            };
+            if name == file || name.ends_with(&format!("/{}", file)) {
+                ret = Some((*start as usize, *l));
+            }
+        }
+        return ret;
+    }
+
+    // Finds the name of the function an instruction is in
+    pub fn functi_at(&self, index: usize) -> Option<String> {
+        for (name, (pos, _)) in &self.functis {
+            // Functions are jumped around, the JMPU is right before them
+            let jmp = pos - 3;
+            let offset = ((self.ops[jmp] as usize) << 16)
+                + ((self.ops[jmp + 1] as usize) << 8)
+                + self.ops[jmp + 2] as usize;
+            if (*pos..jmp + 2 + offset).contains(&index) {
+                return Some(name.clone());
+            }
+        }
+        return None;
+    }
+
+    // Names the function starting at `pos`
+    pub fn functi_name(&self, pos: usize) -> String {
+        for (name, (start, _)) in &self.functis {
+            if *start == pos {
+                return name.clone();
+            }
+        }
+        return "<anonymous>".to_string();
+    }
+
+    // Adds a builtin function that can be called
+    pub fn add_native(&mut self, name: &str) {
+        self.natives.insert(name.to_string());
+    }
+
+    // Adds a global made outside of sack, so it can be called
+    pub fn add_global(&mut self, name: &str) {
+        self.globals.insert(name.to_string());
+    }
+
     pub fn push(&mut self, val: Value) {
         // Get the index, or append
         let index = self.consts.iter().position(|i| i.clone() == val)
@@ -88,6 +207,99 @@ impl Program {
         }
     }
 
+    // Finds the slot of a local, None means it's a global (or a function)
+    fn find_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().flatten().position(|x| x == name)
+    }
+
+    // Pushes a variable, locals use their slot if it fits in a byte
+    fn push_var(&mut self, name: &str) {
+        match self.find_local(name) {
+            Some(slot) if slot <= u8::MAX as usize => {
+                self.ops.push(Opcode::PLV as u8);
+                self.ops.push(slot as u8);
+            },
+            _ => {
+                self.push(Value::str(name));
+                self.ops.push(Opcode::PV as u8);
+            },
+        }
+    }
+
+    // Sets a variable to the head of the stack
+    fn set_var(&mut self, name: &str) {
+        match self.find_local(name) {
+            Some(slot) if slot <= u8::MAX as usize => {
+                self.ops.push(Opcode::SLV as u8);
+                self.ops.push(slot as u8);
+            },
+            _ => {
+                self.push(Value::str(name));
+                self.ops.push(Opcode::SV as u8);
+            },
+        }
+    }
+
+    // Sets keys in a variable, after the value, keys, and key count
+    fn set_var_keys(&mut self, name: &str) {
+        match self.find_local(name) {
+            Some(slot) if slot <= u8::MAX as usize => {
+                self.ops.push(Opcode::SKLV as u8);
+                self.ops.push(slot as u8);
+            },
+            _ => {
+                self.push(Value::str(name));
+                self.ops.push(Opcode::SKV as u8);
+            },
+        }
+    }
+
+    // Declares a variable with the head of the stack
+    fn declare_var(&mut self, name: &str) {
+        self.push(Value::str(name));
+        if self.locals.is_empty() {
+            self.globals.insert(name.to_string());
+            self.ops.push(Opcode::DV as u8);
+            return;
+        }
+        // Redefinitions are left for the VM to error on
+        if self.find_local(name).is_some() {
+            self.ops.push(Opcode::DV as u8);
+            return;
+        }
+        // So are locals with the name of a global, if the global exists
+        let op = if self.globals.contains(name) {
+            Opcode::DV
+        } else {
+            Opcode::DLV
+        };
+        self.locals.last_mut().expect(IMPOSSIBLE_STATE).push(name.to_string());
+        self.ops.push(op as u8);
+    }
+
+    // Leaves everything in `exits` above `to`
+    fn leave(&mut self, to: usize) {
+        for at in (to..self.exits.len()).rev() {
+            let op = match &mut self.exits[at] {
+                // Filled in when the body ends
+                Exit::Body(nops) => {
+                    nops.push(self.ops.len());
+                    Opcode::NOP
+                },
+                Exit::Scope => Opcode::RS,
+                Exit::Try => Opcode::ENDTRY,
+                Exit::Loop(..) => continue,
+            };
+            self.ops.push(op as u8);
+        }
+    }
+
+    // Finds the innermost loop
+    fn find_loop(&self) -> usize {
+        self.exits.iter().rposition(|x| matches!(x, Exit::Loop(..)))
+            .expect(IMPOSSIBLE_STATE)
+    }
+
     pub fn fill_jmp(&mut self, start: usize, mut i: usize) {
         if i == 0 {
             i = self.ops.len() - start - 2;
@@ -98,48 +310,226 @@ impl Program {
     }
 }
 
+// Checks if a call is to `name`
+fn is_named(functi: &ASTNode, name: &str) -> bool {
+    let VarExpr(var, _) = functi else {
+        return false;
+    };
+    return var.rsplit_once("::").is_some_and(|(_, short)| short == name);
+}
+
+// Finds the functions declared anywhere in a node, (name, arg num, span)
+fn find_functis(node: &ASTNode, functis: &mut Vec<(String, i32, Span)>) {
+    if let FunctiStmt(name, fargs, _, span) = node {
+        functis.push((name.clone(), fargs.len() as i32, *span));
+    }
+    let mut find = |node: &ASTNode| find_functis(node, functis);
+    match node {
+        FunctiStmt(_, _, body, _) | FunctiExpr(_, body) => find(body),
+        CallExpr(functi, call_args, _) => {
+            find(functi);
+            call_args.iter().for_each(find);
+        },
+        IndexExpr(lhs, rhs, _) | BinopExpr(lhs, _, rhs, _) => {
+            find(lhs);
+            find(rhs);
+        },
+        UnaryExpr(_, val, _) | LetStmt(_, val) | ReturnStmt(val)
+            | PrivateStmt(val) => find(val),
+        ListExpr(keys, values, _) => {
+            keys.iter().for_each(&mut find);
+            values.iter().for_each(find);
+        },
+        BodyStmt(nodes) => nodes.iter().for_each(find),
+        IfStmt(cond, body, else_part) => {
+            find(cond);
+            find(body);
+            find(else_part);
+        },
+        LoopStmt(_, iter, body) | WhileStmt(iter, body) => {
+            find(iter);
+            find(body);
+        },
+        TryStmt(body, _, catch) => {
+            find(body);
+            find(catch);
+        },
+        _ => {},
+    }
+}
+
+// Prints an error at `span` in `file`
+fn compile_err(args: &Arguments, file: &str, span: &Span, msg: &str) {
+    let stream = Stream{
+        name: file.to_string(), line: span.line as usize,
+        at: span.at as usize, rat: 0, size: span.size as usize,
+    };
+    err(
+        &stream, msg, ErrType::Err,
+        args.extensions.contains(&"color".to_string())
+    );
+}
+
+// Finds the variables used in a function that it doesn't define
+fn find_captures(fargs: &[String], body: &ASTNode) -> Vec<String> {
+    let mut used: Vec<String> = vec![];
+    let mut defined: Vec<String> = fargs.to_vec();
+    find_vars(body, &mut used, &mut defined);
+    used.retain(|x| !defined.contains(x));
+    return used;
+}
+
+// `find` borrows `used`, so the VarExpr check can't be a match guard
+#[allow(clippy::collapsible_match)]
+fn find_vars(node: &ASTNode, used: &mut Vec<String>, defined: &mut Vec<String>) {
+    let mut find = |node: &ASTNode| find_vars(node, used, defined);
+    match node {
+        VarExpr(name, _) => {
+            if !used.contains(name) {
+                used.push(name.clone());
+            }
+        },
+        CallExpr(functi, call_args, _) => {
+            find(functi);
+            call_args.iter().for_each(find);
+        },
+        IndexExpr(list, index, _) => {
+            find(list);
+            find(index);
+        },
+        UnaryExpr(_, val, _) => find(val),
+        BinopExpr(lhs, _, rhs, _) => {
+            find(lhs);
+            find(rhs);
+        },
+        ListExpr(keys, values, _) => {
+            keys.iter().for_each(&mut find);
+            values.iter().for_each(find);
+        },
+        // Nested functions capture from this one
+        FunctiExpr(fargs, body) => {
+            for name in find_captures(fargs, body) {
+                if !used.contains(&name) {
+                    used.push(name);
+                }
+            }
+        },
+        BodyStmt(nodes) => nodes.iter().for_each(find),
+        IfStmt(cond, body, else_part) => {
+            find(cond);
+            find(body);
+            find(else_part);
+        },
+        LetStmt(name, val) => {
+            find(val);
+            defined.push(name.clone());
+        },
+        ReturnStmt(val) => find(val),
+        LoopStmt(var, iter, body) => {
+            find(iter);
+            find(body);
+            defined.push(var.clone());
+        },
+        WhileStmt(cond, body) => {
+            find(cond);
+            find(body);
+        },
+        TryStmt(body, var, catch) => {
+            find(body);
+            find(catch);
+            defined.push(var.clone());
+        },
+        _ => {},
+    }
+}
+
+// Compiles a function, after the FN or LFN
+// Captures are the first locals, then the args
+fn compile_functi(
+    program: &mut Program, args: &mut Arguments,
+    name: &str, fargs: &Vec<String>, captures: Vec<String>, body: &ASTNode
+) -> bool {
+    // Jump around function
+    program.ops.push(Opcode::JMPU as u8);
+    let pos = program.ops.len();
+    program.ops.push(0);
+    program.ops.push(0);
+    program.ops.push(0);
+    // Update compiler state
+    let old_functi = std::mem::replace(&mut program.functi, (
+        name.to_string(), fargs.len() as u8, program.ops.len() as u32
+    ));
+    // Named functions exist before they run, so they can be called directly
+    if !name.is_empty() {
+        program.functis.insert(
+            name.to_string(), (program.ops.len(), fargs.len() as i32)
+        );
+    }
+    let old_exits = std::mem::take(&mut program.exits);
+    let old_locals = std::mem::replace(&mut program.locals, vec![captures]);
+    // Load args
+    for arg in fargs {
+        program.declare_var(arg);
+    }
+    // Compile body
+    let ret = compile_body(program, args, body, true);
+    program.exits = old_exits;
+    program.locals = old_locals;
+    program.functi = old_functi;
+    // Return
+    program.push(Value::None);
+    program.ops.push(Opcode::RET as u8);
+    // Fill jump
+    program.fill_jmp(pos, 0);
+    return ret;
+}
+
 fn compile_unary(
-    program: &mut Program,
-    op: &TokenType, val: &Box<ASTNode>
+    program: &mut Program, args: &mut Arguments,
+    op: &TokenType, val: &ASTNode, span: &Span
 ) -> bool {
     match op {
         // -/!
         TokenType::Minus => {
             program.push(Value::Int(0));
-            if !compile_expr(program, val) {
+            if !compile_expr(program, args, val) {
                 return false;
             }
             program.ops.push(Opcode::SUB as u8);
+            program.mark_span(program.ops.len() - 1, span);
         },
         TokenType::Not => {
-            if !compile_expr(program, val) {
+            if !compile_expr(program, args, val) {
                 return false;
             }
             program.ops.push(Opcode::NOT as u8);
+            program.mark_span(program.ops.len() - 1, span);
         },
         // ++/--
         TokenType::PlusPlus => {
-            if !compile_expr(program, val) {
+            if !compile_expr(program, args, val) {
                 return false;
             }
+            let start = program.ops.len();
             program.push(Value::Int(1));
             program.ops.push(Opcode::ADD as u8);
+            program.mark_span(start, span);
             program.ops.push(Opcode::DUP as u8);
-            if let VarExpr(s) = *val.clone() {
-                program.push(Value::Str(s));
-                program.ops.push(Opcode::SV as u8);
+            if let VarExpr(s, _) = val.clone() {
+                program.set_var(&s);
             }
         },
         TokenType::MinusMinus => {
-            if !compile_expr(program, val) {
+            if !compile_expr(program, args, val) {
                 return false;
             }
+            let start = program.ops.len();
             program.push(Value::Int(1));
             program.ops.push(Opcode::SUB as u8);
+            program.mark_span(start, span);
             program.ops.push(Opcode::DUP as u8);
-            if let VarExpr(s) = *val.clone() {
-                program.push(Value::Str(s));
-                program.ops.push(Opcode::SV as u8);
+            if let VarExpr(s, _) = val.clone() {
+                program.set_var(&s);
             }
         },
         _ => panic!("{}", IMPOSSIBLE_STATE),
@@ -147,21 +537,45 @@ fn compile_unary(
     return true;
 }
 
-fn compile_set(program: &mut Program, var: &ASTNode) -> bool {
+fn compile_set(
+    program: &mut Program, args: &mut Arguments, var: &ASTNode
+) -> bool {
     // Recursively set
-    if let VarExpr(s) = var.clone() {
-        program.push(Value::Str(s));
-        program.ops.push(Opcode::SV as u8);
-    } else if let IndexExpr(list, index) = var.clone() {
-        if !compile_expr(program, &list) {
+    if let VarExpr(s, span) = var {
+        let start = program.ops.len();
+        program.set_var(s);
+        program.mark_span(start, span);
+        return true;
+    }
+    // Find the variable, `a[1][2]` is `a` with the keys `[1, 2]`
+    let mut keys = vec![];
+    let mut base = var;
+    while let IndexExpr(list, index, _) = base {
+        keys.push(index);
+        base = list;
+    }
+    if let (VarExpr(name, _), IndexExpr(_, _, span)) = (base, var) {
+        // Set it in place, so the list doesn't get copied
+        for key in keys.iter().rev() {
+            if !compile_expr(program, args, key) {
+                return false;
+            }
+        }
+        program.push(Value::Int(keys.len() as i64));
+        let start = program.ops.len();
+        program.set_var_keys(name);
+        program.mark_span(start, span);
+    } else if let IndexExpr(list, index, span) = var.clone() {
+        if !compile_expr(program, args, &list) {
             return false;
         }
-        if !compile_expr(program, &index) {
+        if !compile_expr(program, args, &index) {
             return false;
         }
         program.ops.push(Opcode::SKY as u8);
+        program.mark_span(program.ops.len() - 1, &span);
         // Indexes are attached to something, make sure it reattaches
-        if !compile_set(program, &list) {
+        if !compile_set(program, args, &list) {
             return false;
         }
     }
@@ -169,15 +583,15 @@ fn compile_set(program: &mut Program, var: &ASTNode) -> bool {
 }
 
 fn compile_short_binop(
-    program: &mut Program,
-    lhs: &Box<ASTNode>, op: &TokenType, rhs: &Box<ASTNode>,
+    program: &mut Program, args: &mut Arguments,
+    lhs: &ASTNode, op: &TokenType, rhs: &ASTNode,
     clean: bool
 ) -> bool {
     // Compiles short circuiting operators (&& and ||)
     // Uses jump instructions to:
     // Turn `a() && b()` into `r = a(); if r { r = b() }; r`
     // Turn `a() || b()` into `r = a(); if !r { r = b() }; r`
-    if !compile_expr(program, lhs) {
+    if !compile_expr(program, args, lhs) {
         return false;
     }
     program.ops.push(Opcode::DUP as u8);
@@ -192,7 +606,7 @@ fn compile_short_binop(
     program.ops.push(0);
     // It's 'b' (the left)
     program.ops.push(Opcode::DEL as u8);
-    if !compile_expr(program, rhs) {
+    if !compile_expr(program, args, rhs) {
         return false;
     }
     // End the jump
@@ -204,25 +618,40 @@ fn compile_short_binop(
 }
 
 fn compile_binop(
-    program: &mut Program,
-    lhs: &Box<ASTNode>, op: &TokenType, rhs: &Box<ASTNode>,
+    program: &mut Program, args: &mut Arguments,
+    lhs: &ASTNode, op: &TokenType, rhs: &ASTNode, span: &Span,
     clean: bool
 ) -> bool {
     // Short circuiting ops are special
     if op == &TokenType::And || op == &TokenType::Or {
-        return compile_short_binop(program, lhs, op, rhs, clean);
+        return compile_short_binop(program, args, lhs, op, rhs, clean);
+    }
+    // `local += x` adds in place, so lists aren't copied
+    if let (TokenType::PlusEquals, VarExpr(name, _)) = (op, lhs) {
+        let slot = program.find_local(name);
+        if let Some(slot) = slot.filter(|x| *x <= u8::MAX as usize) {
+            if !compile_expr(program, args, rhs) {
+                return false;
+            }
+            let start = program.ops.len();
+            program.ops.push(Opcode::ALV as u8);
+            program.ops.push(slot as u8);
+            program.mark_span(start, span);
+            return true;
+        }
     }
     // Compile sides
     if op != &TokenType::Equals {
         // No need to compile the value if it will just be reassigned
-        if !compile_expr(program, lhs) {
+        if !compile_expr(program, args, lhs) {
             return false;
         }
     }
-    if !compile_expr(program, rhs) {
+    if !compile_expr(program, args, rhs) {
         return false;
     }
     // Compile op
+    let start = program.ops.len();
     match op {
         // Simple single instructions
         TokenType::Plus | TokenType::PlusEquals => {
@@ -278,12 +707,13 @@ fn compile_binop(
         TokenType::Equals => {},
         _ => panic!("That operator isn't implemented!"),
     };
+    program.mark_span(start, span);
     // Set the variable
     if let TokenType::PlusEquals | TokenType::MinusEquals
         | TokenType::TimesEquals | TokenType::DivEquals
         | TokenType::ModEquals | TokenType::Equals = op.clone()
     {
-        if !compile_set(program, lhs) {
+        if !compile_set(program, args, lhs) {
             return false;
         }
 
@@ -294,21 +724,70 @@ fn compile_binop(
     return true;
 }
 
-fn compile_expr(program: &mut Program, node: &ASTNode) -> bool {
+// Compiles the call op for a call by name, after the args and arg count
+// Functions are checked first, then builtins, then variables
+fn compile_named_call(
+    program: &mut Program, args: &mut Arguments,
+    name: &str, call_args: &[ASTNode], span: &Span
+) -> bool {
+    let (file, short) = name.split_once("::").expect(IMPOSSIBLE_STATE);
+    let short = short.to_string();
+    let arg_num = program.new_functis.get(&short).copied()
+        .or_else(|| program.functis.get(&short).map(|(_, x)| *x));
+    if let Some(arg_num) = arg_num {
+        if arg_num != call_args.len() as i32 {
+            let msg = args_err(&short, call_args.len(), arg_num as usize);
+            compile_err(args, file, span, &msg);
+            return false;
+        }
+        if args.is_repl {
+            // The REPL can redefine functions, so they're looked up when run
+            program.push(Value::str(short));
+            program.ops.push(Opcode::CALL as u8);
+        } else {
+            // Sack functions are called directly, filled in at the end
+            program.ops.push(Opcode::CALLD as u8);
+            program.calls.push((program.ops.len(), short));
+            program.ops.extend([0, 0, 0]);
+        }
+    } else if program.natives.contains(&short) {
+        program.push(Value::str(short));
+        program.ops.push(Opcode::CALL as u8);
+    } else if program.find_local(name).is_some()
+        || program.globals.contains(name) || program.new_globals.contains(name)
+    {
+        program.push_var(name);
+        program.ops.push(Opcode::CALLV as u8);
+    } else {
+        let msg = format!("no function called \"{}\"", short);
+        compile_err(args, file, span, &msg);
+        return false;
+    }
+    return true;
+}
+
+fn compile_expr(
+    program: &mut Program, args: &mut Arguments, node: &ASTNode
+) -> bool {
     match node {
         // Values
-        VarExpr(val) => {
-            program.push(Value::Str(val.clone()));
-            program.ops.push(Opcode::PV as u8);
+        VarExpr(val, span) => {
+            let start = program.ops.len();
+            program.push_var(val);
+            program.mark_span(start, span);
         },
         StringExpr(val) => {
-            program.push(Value::Str(val.clone()));
+            program.push(Value::str(val.clone()));
         },
         NumberExpr(val) => {
-            program.push(Value::Int(*val));
+            program.push(Value::from_big(val.clone()));
         },
         DecimalExpr(val) => {
-            program.push(Value::Float(*val));
+            if args.extensions.contains(&"f32-decimals".to_string()) {
+                program.push(Value::Float(*val as f32 as f64));
+            } else {
+                program.push(Value::Float(*val));
+            }
         },
         BoolExpr(val) => {
             program.push(Value::Bool(*val));
@@ -320,37 +799,62 @@ fn compile_expr(program: &mut Program, node: &ASTNode) -> bool {
             program.push(Value::Byte(*val));
         },
         // Binop/unary
-        BinopExpr(lhs, op, rhs) => {
-            return compile_binop(program, lhs, op, rhs, false);
+        BinopExpr(lhs, op, rhs, span) => {
+            return compile_binop(program, args, lhs, op, rhs, span, false);
         }
-        UnaryExpr(op, val) => {
-            return compile_unary(program, op, val);
+        UnaryExpr(op, val, span) => {
+            return compile_unary(program, args, op, val, span);
         },
         // Calls
-        CallExpr(name, args) => {
-            // Push the call. Name, arg count, args (in reverse order)
-            for arg in args {
-                if !compile_expr(program, arg) {
+        CallExpr(functi, call_args, span) => {
+            // Push the call. Args, arg count, name or function
+            for arg in call_args {
+                if !compile_expr(program, args, arg) {
                     return false;
                 }
             }
-            program.push(Value::Int(args.len() as i32));
-            program.push(Value::Str(name.clone()));
-            program.ops.push(Opcode::CALL as u8);
+            program.push(Value::Int(call_args.len() as i64));
+            if let VarExpr(name, _) = &**functi {
+                let start = program.ops.len();
+                if !compile_named_call(program, args, name, call_args, span) {
+                    return false;
+                }
+                program.mark_span(start, span);
+            } else {
+                if !compile_expr(program, args, functi) {
+                    return false;
+                }
+                program.ops.push(Opcode::CALLV as u8);
+                program.mark_span(program.ops.len() - 1, span);
+            }
+        },
+        // Anonymous functions
+        FunctiExpr(fargs, body) => {
+            // Only locals are captured, globals are looked up by name
+            let mut captures = find_captures(fargs, body);
+            captures.retain(|x| program.find_local(x).is_some());
+            for name in &captures {
+                program.push(Value::str(name.clone()));
+            }
+            program.push(Value::Int(captures.len() as i64));
+            program.push(Value::Int(fargs.len() as i64));
+            program.ops.push(Opcode::LFN as u8);
+            // Anonymous functions can't be tail called by name
+            return compile_functi(program, args, "", fargs, captures, body);
         },
         // List
         ListExpr(keys, values, fast) => {
             // Build the list
             for at in 0..values.len() {
-                if !compile_expr(program, &values[at]) {
+                if !compile_expr(program, args, &values[at]) {
                     return false;
                 }
-                if !*fast {
-                    program.push(Value::Str(keys[at].clone()));
+                if !*fast && !compile_expr(program, args, &keys[at]) {
+                    return false;
                 }
             }
             // Push
-            program.push(Value::Int(values.len() as i32));
+            program.push(Value::Int(values.len() as i64));
             if *fast {
                 program.ops.push(Opcode::LFL as u8);
             } else {
@@ -358,15 +862,16 @@ fn compile_expr(program: &mut Program, node: &ASTNode) -> bool {
             }
         },
         // Indexes
-        IndexExpr(val, index) => {
+        IndexExpr(val, index, span) => {
             // Push
-            if !compile_expr(program, val) {
+            if !compile_expr(program, args, val) {
                 return false;
             }
-            if !compile_expr(program, index) {
+            if !compile_expr(program, args, index) {
                 return false;
             }
             program.ops.push(Opcode::INX as u8);
+            program.mark_span(program.ops.len() - 1, span);
         },
         _ => {
             panic!("Unknown token! {:?}", node);
@@ -385,6 +890,8 @@ fn _compile_body(
     program.needs_scope = false;
     if !manual_scope {
         program.ops.push(Opcode::NOP as u8);
+        program.exits.push(Exit::Body(vec![]));
+        program.locals.push(vec![]);
     }
     // Compile all nodes
     for node in nodes {
@@ -394,9 +901,19 @@ fn _compile_body(
         }
     }
     // Raise scope
-    if !manual_scope && program.needs_scope {
-        program.ops.push(Opcode::RS as u8);
-        program.ops[scope_pos] = Opcode::LEVI as u8;
+    if !manual_scope {
+        program.locals.pop();
+        let Some(Exit::Body(nops)) = program.exits.pop() else {
+            panic!("{}", IMPOSSIBLE_STATE);
+        };
+        if program.needs_scope {
+            program.ops.push(Opcode::RS as u8);
+            program.ops[scope_pos] = Opcode::LEVI as u8;
+            // Breaks and continues need to raise it too
+            for nop in nops {
+                program.ops[nop] = Opcode::RS as u8;
+            }
+        }
     }
     program.needs_scope = old_needs_scope;
     return true;
@@ -419,14 +936,17 @@ fn compile_stmt(
     match node {
         // Statements
         LetStmt(name, val) => {
-            compile_expr(program, val);
-            program.push(Value::Str(name.to_string()));
-            program.ops.push(Opcode::DV as u8);
+            if !compile_expr(program, args, val) {
+                return false;
+            }
+            program.declare_var(name);
             program.needs_scope = true;
         },
         IfStmt(cond, body, else_part) => {
             // The condition must be a expr, so no need to match against stmts
-            compile_expr(program, cond);
+            if !compile_expr(program, args, cond) {
+                return false;
+            }
 
             // This is for when boolean not is forgotten
             if **body == Nop {
@@ -438,7 +958,9 @@ fn compile_stmt(
                 program.ops.push(0);
                 program.ops.push(0);
                 // Compile body
-                compile_stmt(program, args, else_part, false);
+                if !compile_stmt(program, args, else_part, false) {
+                    return false;
+                }
                 program.fill_jmp(pos, 0);
                 return true;
             }
@@ -450,7 +972,9 @@ fn compile_stmt(
             program.ops.push(0);
             program.ops.push(0);
             // Compile true part
-            compile_body(program, args, body, false);
+            if !compile_body(program, args, body, false) {
+                return false;
+            }
             let offset = program.ops.len() - pos - 2;
 
             // The else
@@ -463,7 +987,9 @@ fn compile_stmt(
                 program.ops.push(0);
                 program.ops.push(0);
                 // Compile else part
-                compile_stmt(program, args, else_part, false);
+                if !compile_stmt(program, args, else_part, false) {
+                    return false;
+                }
                 program.fill_jmp(pos, 0);
             } else {
                 program.fill_jmp(pos, offset);
@@ -471,10 +997,13 @@ fn compile_stmt(
         },
         LoopStmt(var, iter, body) => {
             // Load iter
-            compile_expr(program, iter);
+            if !compile_expr(program, args, iter) {
+                return false;
+            }
             program.ops.push(Opcode::TITR as u8);
             let pos = program.ops.len();
             program.ops.push(Opcode::NXT as u8);
+            program.exits.push(Exit::Loop(vec![], pos));
 
             // Exit jump
             program.ops.push(Opcode::JMPNT as u8);
@@ -485,15 +1014,26 @@ fn compile_stmt(
 
             // Lower scope
             program.ops.push(Opcode::LEVI as u8);
+            program.exits.push(Exit::Scope);
+            program.locals.push(vec![]);
 
-            // Set the loop var
-            program.push(Value::Str(var.to_string()));
-            program.ops.push(Opcode::DOS as u8);
+            // Set the loop var, existing variables are reused
+            if program.find_local(var).is_some()
+                || program.globals.contains(var)
+            {
+                program.set_var(var);
+            } else {
+                program.declare_var(var);
+            }
 
             // Body
-            compile_body(program, args, body, true);
+            if !compile_body(program, args, body, true) {
+                return false;
+            }
 
             // Raise scope
+            program.locals.pop();
+            program.exits.pop();
             program.ops.push(Opcode::RS as u8);
 
             // Backwards jump
@@ -504,12 +1044,20 @@ fn compile_stmt(
             program.fill_jmp(program.ops.len() - 3, program.ops.len() - pos - 1);
             // Clean up the iter
             program.fill_jmp(offpos, 0);
+            let Some(Exit::Loop(breaks, ..)) = program.exits.pop() else {
+                panic!("{}", IMPOSSIBLE_STATE);
+            };
+            for jmp in breaks {
+                program.fill_jmp(jmp, 0);
+            }
             program.ops.push(Opcode::DEL as u8);
         },
         WhileStmt(cond, body) => {
             // Start, exit jump + cond
             let pos = program.ops.len();
-            compile_expr(program, cond);
+            if !compile_expr(program, args, cond) {
+                return false;
+            }
             program.ops.push(Opcode::JMPNT as u8);
             let offpos = program.ops.len();
             program.ops.push(0);
@@ -517,7 +1065,10 @@ fn compile_stmt(
             program.ops.push(0);
 
             // Compile body
-            compile_body(program, args, body, false);
+            program.exits.push(Exit::Loop(vec![], pos));
+            if !compile_body(program, args, body, false) {
+                return false;
+            }
 
             // Backwards jump
             program.ops.push(Opcode::JMPB as u8);
@@ -526,45 +1077,54 @@ fn compile_stmt(
             program.ops.push(0);
             program.fill_jmp(program.ops.len() - 3, program.ops.len() - pos - 1);
             program.fill_jmp(offpos, 0);
+            let Some(Exit::Loop(breaks, ..)) = program.exits.pop() else {
+                panic!("{}", IMPOSSIBLE_STATE);
+            };
+            for jmp in breaks {
+                program.fill_jmp(jmp, 0);
+            }
         },
-        BodyStmt(nodes) => return _compile_body(program, args, nodes, false),
-        FunctiStmt(name, fargs, body) => {
-            // Declare function
-            program.push(Value::Int(fargs.len() as i32));
-            program.push(Value::Str(name.to_string()));
-            program.ops.push(Opcode::FN as u8);
-            // Jump around function
+        BreakStmt => {
+            let at = program.find_loop();
+            program.leave(at + 1);
+            // Jump to the end, the iter is cleaned up there
             program.ops.push(Opcode::JMPU as u8);
-            let pos = program.ops.len();
+            let Exit::Loop(breaks, ..) = &mut program.exits[at] else {
+                panic!("{}", IMPOSSIBLE_STATE);
+            };
+            breaks.push(program.ops.len());
             program.ops.push(0);
             program.ops.push(0);
             program.ops.push(0);
-            // Update compiler state
-            program.functi = (
-                name.clone(), fargs.len() as u8, program.ops.len() as u32
-            );
-            // Load args
-            for arg in fargs {
-                program.push(Value::Str(arg.to_string()));
-                program.ops.push(Opcode::DV as u8);
-            }
-            // Compile body
-            compile_body(program, args, body, true);
-            // Return
-            program.push(Value::None);
-            program.ops.push(Opcode::RET as u8);
-            // Fill jump
-            program.fill_jmp(pos, 0);
+        },
+        ContinueStmt => {
+            let at = program.find_loop();
+            program.leave(at + 1);
+            // Jump back to the start
+            let Exit::Loop(_, pos) = program.exits[at] else {
+                panic!("{}", IMPOSSIBLE_STATE);
+            };
+            program.ops.push(Opcode::JMPB as u8);
+            program.ops.push(0);
+            program.ops.push(0);
+            program.ops.push(0);
+            program.fill_jmp(program.ops.len() - 3, program.ops.len() - pos - 1);
+        },
+        BodyStmt(nodes) => return _compile_body(program, args, nodes, false),
+        FunctiStmt(name, fargs, body, _) => {
+            return compile_functi(program, args, name, fargs, vec![], body);
         },
         ReturnStmt(ret) => {
             match *ret.clone() {
-                CallExpr(name, args) if (
-                    name == program.functi.0
-                    && args.len() == program.functi.1 as usize
+                // Tail calls would skip the catch
+                CallExpr(functi, call_args, _) if (
+                    !program.exits.iter().any(|x| matches!(x, Exit::Try))
+                    && is_named(&functi, &program.functi.0)
+                    && call_args.len() == program.functi.1 as usize
                 ) => {
                     // Tail call
-                    for ref arg in args.iter().rev() {
-                        if !compile_expr(program, arg) {
+                    for arg in call_args.iter().rev() {
+                        if !compile_expr(program, args, arg) {
                             return false;
                         }
                     }
@@ -579,15 +1139,52 @@ fn compile_stmt(
                 }
                 // Compile return value
                 _ => {
-                    if !compile_expr(program, ret) {
+                    if !compile_expr(program, args, ret) {
                         return false;
                     }
                 }
             };
+            // Leave try blocks
+            for _ in program.exits.iter().filter(|x| matches!(x, Exit::Try)) {
+                program.ops.push(Opcode::ENDTRY as u8);
+            }
             // Return return value
             program.ops.push(Opcode::RET as u8);
         },
-        ImportStmt() => {
+        TryStmt(body, var, catch) => {
+            // Catch errors
+            program.ops.push(Opcode::TRY as u8);
+            let catch_pos = program.ops.len();
+            program.ops.push(0);
+            program.ops.push(0);
+            program.ops.push(0);
+            program.exits.push(Exit::Try);
+            if !compile_body(program, args, body, false) {
+                return false;
+            }
+            program.exits.pop();
+            program.ops.push(Opcode::ENDTRY as u8);
+            // Skip the catch
+            program.ops.push(Opcode::JMPU as u8);
+            let end_pos = program.ops.len();
+            program.ops.push(0);
+            program.ops.push(0);
+            program.ops.push(0);
+            // Catch, the VM pushes the error
+            program.fill_jmp(catch_pos, 0);
+            program.ops.push(Opcode::LEVI as u8);
+            program.exits.push(Exit::Scope);
+            program.locals.push(vec![]);
+            program.declare_var(var);
+            if !compile_body(program, args, catch, true) {
+                return false;
+            }
+            program.locals.pop();
+            program.exits.pop();
+            program.ops.push(Opcode::RS as u8);
+            program.fill_jmp(end_pos, 0);
+        },
+        ImportStmt(_) => {
             program.file_table.push((
                 program.inc_start, program.ops.len() as u32, args.name.clone()
             ));
@@ -599,17 +1196,20 @@ fn compile_stmt(
             ));
             program.inc_start = program.ops.len() as u32;
         },
+        LineStmt(line) => program.mark_line(*line),
+        // Privacy is only checked by the parser
+        PrivateStmt(stmt) => return compile_stmt(program, args, stmt, dirty),
 
         Nop => {
             // Nop isn't turned into the NOP instruction because it's useless
         },
         // Expressions
         // Binops don't always return, so let them manage cleaning the stack
-        BinopExpr(lhs, op, rhs) => {
-            return compile_binop(program, lhs, op, rhs, !dirty);
+        BinopExpr(lhs, op, rhs, span) => {
+            return compile_binop(program, args, lhs, op, rhs, span, !dirty);
         },
         _ => {
-            let ret = compile_expr(program, node);
+            let ret = compile_expr(program, args, node);
             if !dirty {
                 // Remove unused values from the stack
                 program.ops.push(Opcode::DEL as u8);
@@ -627,17 +1227,77 @@ pub fn compile(
         return true;
     }
     program.inc_start = program.ops.len() as u32;
-    // Compile
-    for node in &ast[..ast.len()-1] {
-        if !compile_stmt(program, args, node, false) {
-            return false;
+    // Anything left from a failed compile (in the REPL)
+    program.exits.clear();
+    program.locals.clear();
+    program.calls.clear();
+    // Functions and globals can be used before they're declared
+    program.new_functis.clear();
+    program.new_globals.clear();
+    // Each function can only be defined once, calls are fixed when compiled
+    // The REPL can redefine functions from earlier lines
+    let mut files = vec![args.name.clone()];
+    let mut defined = true;
+    for node in &ast {
+        match node {
+            ImportStmt(file) => files.push(file.clone()),
+            EndImportStmt(_) => { files.pop(); },
+            _ => {},
+        }
+        let mut functis = vec![];
+        find_functis(node, &mut functis);
+        for (name, arg_num, span) in functis {
+            if (program.functis.contains_key(&name) && !args.is_repl)
+                || program.new_functis.insert(name.clone(), arg_num).is_some()
+            {
+                let file = files.last().expect(IMPOSSIBLE_STATE);
+                // Private functions are `file:name`
+                let short = name.rsplit(':').next().unwrap_or(&name);
+                let msg = format!("function \"{}\" is already defined", short);
+                compile_err(args, file, &span, &msg);
+                defined = false;
+            }
         }
+        let stmt = if let PrivateStmt(stmt) = node { stmt } else { node };
+        if let LetStmt(name, _) = stmt {
+            program.new_globals.insert(name.clone());
+        }
+    }
+    if !defined {
+        return false;
     }
+    // A failed compile leaves the program as it was, so the REPL can go on
+    let old_lens = (
+        program.ops.len(), program.line_table.len(),
+        program.file_table.len(), program.span_table.len(),
+    );
+    let old_line = program.line_table.last().cloned();
+    let old_functis = program.functis.clone();
+    let old_globals = program.globals.clone();
+    // Compile
     // If repl, compile the last value without cleaning up
     // Else just compile normally
-    if !compile_stmt(program, args, ast.last().unwrap(), args.is_repl) {
+    let last = ast.len() - 1;
+    let compiled = ast.iter().enumerate().all(|(at, node)| {
+        compile_stmt(program, args, node, at == last && args.is_repl)
+    });
+    if !compiled {
+        program.ops.truncate(old_lens.0);
+        program.line_table.truncate(old_lens.1);
+        if let (Some(line), Some(last)) = (old_line, program.line_table.last_mut()) {
+            *last = line;
+        }
+        program.file_table.truncate(old_lens.2);
+        program.span_table.truncate(old_lens.3);
+        program.functis = old_functis;
+        program.globals = old_globals;
         return false;
     }
+    // Fill in direct calls
+    for (pos, name) in std::mem::take(&mut program.calls) {
+        let (address, _) = program.functis[&name];
+        program.fill_jmp(pos, address);
+    }
     // Jumps go onto the next instruction, so a nop is needed at the end
     program.ops.push(Opcode::NOP as u8);
     // End file
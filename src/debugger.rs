@@ -0,0 +1,391 @@
+use crate::Arguments;
+use crate::common::{get_line, print_err, set_repl_line, ErrType, Stream};
+use crate::compiler::compile;
+use crate::lexer::lex;
+use crate::parser::{parse, ASTNode};
+use crate::repl::LineEditor;
+use crate::value::{Value, View};
+use crate::vm::{report_err, step, Vm};
+
+use rustyline::error::ReadlineError;
+
+const HELP: &str = "\
+Commands:
+    run, r\t\t\tstart (or restart) the program
+    continue, c\t\trun until a breakpoint or the end
+    step, s\t\t\trun until the next line, entering calls
+    next, n\t\t\trun until the next line, skipping over calls
+    finish\t\t\trun until the current function returns
+    break, b [file:]line\tset a breakpoint
+    delete, d [n]\t\tdelete breakpoint n (or all of them)
+    info breakpoints\tlist breakpoints
+    locals\t\t\tprint the local variables
+    globals\t\t\tprint the global variables
+    backtrace, bt\t\tprint the call stack
+    print, p expr\t\tevaluate expr in the current frame
+    list, l\t\t\tprint the source around the current line
+    quit, q\t\t\texit the debugger
+An empty line repeats the last command.";
+
+// Where execution should stop (besides breakpoints and the end)
+#[derive(Clone, Copy)]
+enum Until {
+    // Only breakpoints
+    Breakpoint,
+    // Any new line
+    Line,
+    // A new line at or above a call depth
+    LineAt(usize),
+    // A return below a call depth
+    Return(usize),
+}
+
+struct Breakpoint {
+    id: usize,
+    file: String,
+    line: u32,
+    at: usize,
+}
+
+struct Debugger {
+    args: Arguments,
+    ast: Vec<ASTNode>,
+    vm: Vm,
+    color: bool,
+    breakpoints: Vec<Breakpoint>,
+    next_id: usize,
+    // If the program has started and hasn't ended
+    running: bool,
+}
+
+// Prints a value for inspection, strings are quoted
+fn show(val: &Value) -> String {
+    if let View::Str(s) = val.view() {
+        return format!("{:?}", s);
+    }
+    return val.to_string().unwrap_or_else(|_| format!("{:?}", val));
+}
+
+// Gets a line of source, None if it doesn't exist
+fn source_line(file: &str, line: u32) -> Option<String> {
+    return get_line(&Stream{
+        name: file.to_string(), line: line as usize, at: 0, rat: 0, size: 0
+    });
+}
+
+impl Debugger {
+    fn new(args: &Arguments, ast: Vec<ASTNode>) -> Option<Debugger> {
+        let mut ret = Debugger {
+            args: args.clone(), ast, vm: Vm::new(args.clone()),
+            color: args.extensions.contains(&"color".to_string()),
+            breakpoints: vec![], next_id: 1, running: false,
+        };
+        if !ret.load() {
+            return None;
+        }
+        return Some(ret);
+    }
+
+    // Makes a fresh VM with the program loaded
+    fn load(&mut self) -> bool {
+        let mut args = self.args.clone();
+        self.vm = Vm::new(args.clone());
+        // Fix import path
+        self.vm.program.path = args.path.clone();
+        self.vm.program.path.pop();
+        return compile(self.ast.clone(), &mut args, &mut self.vm.program);
+    }
+
+    fn err(&self, msg: &str) {
+        print_err(msg, ErrType::Err, self.color);
+    }
+
+    // The file and line of the current instruction
+    fn location(&mut self) -> (String, u32) {
+        let (line, file) = self.vm.program.get_info(self.vm.at as u32);
+        return (file, line);
+    }
+
+    fn print_location(&mut self) {
+        let (file, line) = self.location();
+        let functi = self.vm.program.functi_at(self.vm.at)
+            .map(|x| format!(" in {}()", x)).unwrap_or_default();
+        println!("{}:{}{}", file, line, functi);
+        if let Some(src) = source_line(&file, line) {
+            println!("    {} | {}", line, src);
+        }
+    }
+
+    // Runs until `until` (or a breakpoint) is reached
+    fn resume(&mut self, until: Until, check_first: bool) {
+        if !self.running {
+            self.err("the program is not being run");
+            return;
+        }
+        let mut first = !check_first;
+        loop {
+            if !first {
+                // Breakpoints
+                let at = self.vm.at;
+                if let Some(b) = self.breakpoints.iter().find(|b| b.at == at) {
+                    println!("Breakpoint {}, {}:{}", b.id, b.file, b.line);
+                    break;
+                }
+                let cur_depth = self.vm.get_call_frames().len();
+                let line_start = self.vm.program.is_line_start(at as u32);
+                let stop = match until {
+                    Until::Breakpoint => false,
+                    Until::Line => line_start,
+                    Until::LineAt(d) => line_start && cur_depth <= d,
+                    Until::Return(d) => cur_depth < d,
+                };
+                if stop {
+                    if let Until::Return(_) = until {
+                        if let Some(val) = self.vm.peek() {
+                            println!("Returned {}", show(val));
+                        }
+                    }
+                    break;
+                }
+            }
+            first = false;
+            // Run
+            match step(&mut self.vm) {
+                Ok(true) => {},
+                Ok(false) => {
+                    println!("Program finished");
+                    self.running = false;
+                    return;
+                },
+                Err(s) => {
+                    report_err(&mut self.vm, &s);
+                    self.running = false;
+                    return;
+                }
+            }
+        }
+        self.print_location();
+    }
+
+    fn run(&mut self) {
+        if !self.load() {
+            return;
+        }
+        self.running = true;
+        self.resume(Until::Breakpoint, true);
+    }
+
+    fn add_breakpoint(&mut self, loc: Option<&str>) {
+        let Some(loc) = loc else {
+            self.err("expected a location, like `break file.sk:12`");
+            return;
+        };
+        // Split into file and line, the file defaults to the current one
+        let (file, line) = match loc.rsplit_once(':') {
+            Some((file, line)) => (file.to_string(), line),
+            None => (self.location().0, loc),
+        };
+        let file = if file == "Unknown File" {
+            self.args.name.clone()
+        } else {
+            file
+        };
+        let Ok(line) = line.parse::<u32>() else {
+            self.err(format!("invalid line number: {}", line).as_str());
+            return;
+        };
+        // Find the code
+        let Some((at, line)) = self.vm.program.find_line(&file, line) else {
+            self.err(format!("no code at {}:{}", file, line).as_str());
+            return;
+        };
+        println!("Breakpoint {} at {}:{}", self.next_id, file, line);
+        self.breakpoints.push(Breakpoint{id: self.next_id, file, line, at});
+        self.next_id += 1;
+    }
+
+    fn delete_breakpoint(&mut self, id: Option<&str>) {
+        let Some(id) = id else {
+            self.breakpoints.clear();
+            return;
+        };
+        let old_len = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.id.to_string() != id);
+        if old_len == self.breakpoints.len() {
+            self.err(format!("no breakpoint {}", id).as_str());
+        }
+    }
+
+    fn print_vars(&self, vars: Vec<(String, Value)>) {
+        if vars.is_empty() {
+            println!("No variables");
+        }
+        for (name, val) in vars {
+            println!("{} = {}", Vm::unmangle(&name), show(&val));
+        }
+    }
+
+    fn backtrace(&mut self) {
+        let frames = self.vm.get_call_frames().clone();
+        // Each frame is at the call site of the frame inside it
+        let mut at = self.vm.at;
+        for (num, frame) in frames.iter().rev().enumerate() {
+            let (line, file) = self.vm.program.get_info(at as u32);
+            let args = frame.args.iter().map(show).collect::<Vec<String>>()
+                .join(", ");
+            let name = self.vm.program.functi_name(frame.pos);
+            println!("#{} {}({}) at {}:{}", num, name, args, file, line);
+            at = frame.call_site;
+        }
+        let (line, file) = self.vm.program.get_info(at as u32);
+        println!("#{} <top level> at {}:{}", frames.len(), file, line);
+    }
+
+    fn list(&mut self) {
+        let (file, line) = self.location();
+        let start = if line > 5 { line - 5 } else { 1 };
+        for at in start..start + 11 {
+            let Some(src) = source_line(&file, at) else {
+                break;
+            };
+            let marker = if at == line { ">" } else { " " };
+            println!("{} {:>4} | {}", marker, at, src);
+        }
+    }
+
+    // Evaluates an expression in the current frame
+    fn eval(&mut self, src: &str) {
+        let source = src.to_string() + ";";
+        set_repl_line(&source);
+        let Some(tokens) = lex(
+            &source, "<stdin>".to_string(), true, self.color
+        ) else {
+            return;
+        };
+        // Variables are mangled with the name of the file they're in
+        let mut args = self.args.clone();
+        args.name = self.location().0;
+        args.is_repl = true;
+        let Some(ast) = parse(tokens, &mut args) else {
+            return;
+        };
+        // Compile after the end of the program, then go back
+        let program = self.vm.program.clone();
+        let state = self.vm.save_state();
+        // Errors shouldn't jump into the program's catch blocks
+        let handlers = std::mem::take(&mut self.vm.handlers);
+        let stack_len = self.vm.stack_len();
+        if compile(ast, &mut args, &mut self.vm.program) {
+            self.vm.at = program.ops.len();
+            loop {
+                match step(&mut self.vm) {
+                    Ok(true) => {},
+                    Ok(false) => {
+                        if self.vm.stack_len() > stack_len {
+                            println!("{}", show(&self.vm.pop()));
+                        }
+                        break;
+                    },
+                    Err(s) => {
+                        self.err(&s);
+                        break;
+                    }
+                }
+            }
+        }
+        self.vm.program = program;
+        self.vm.restore_state(&state);
+        self.vm.handlers = handlers;
+    }
+
+    // Runs a command, returns false to quit
+    fn command(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let Some(cmd) = words.next() else {
+            return true;
+        };
+        let arg = words.next();
+        match cmd {
+            "r" | "run" => self.run(),
+            "c" | "continue" => self.resume(Until::Breakpoint, false),
+            "s" | "step" => self.resume(Until::Line, false),
+            "n" | "next" => {
+                let depth = self.vm.get_call_frames().len();
+                self.resume(Until::LineAt(depth), false);
+            },
+            "finish" => {
+                let depth = self.vm.get_call_frames().len();
+                if depth == 0 {
+                    self.err("\"finish\" not meaningful in the outermost frame");
+                } else {
+                    self.resume(Until::Return(depth), false);
+                }
+            },
+            "b" | "break" => self.add_breakpoint(arg),
+            "d" | "delete" => self.delete_breakpoint(arg),
+            "info" if arg == Some("breakpoints") || arg == Some("b") => {
+                if self.breakpoints.is_empty() {
+                    println!("No breakpoints");
+                }
+                for b in &self.breakpoints {
+                    println!("{}: {}:{}", b.id, b.file, b.line);
+                }
+            },
+            "locals" => self.print_vars(self.vm.get_locals()),
+            "info" if arg == Some("locals") =>
+                self.print_vars(self.vm.get_locals()),
+            "globals" => self.print_vars(self.vm.get_globals()),
+            "info" if arg == Some("globals") =>
+                self.print_vars(self.vm.get_globals()),
+            "bt" | "backtrace" | "where" => self.backtrace(),
+            "p" | "print" => {
+                let expr = line.trim_start()[cmd.len()..].trim();
+                if expr.is_empty() {
+                    self.err("expected an expression");
+                } else {
+                    self.eval(expr);
+                }
+            },
+            "l" | "list" => self.list(),
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return false,
+            _ => self.err(
+                format!("unknown command \"{}\", try \"help\"", cmd).as_str()
+            ),
+        }
+        return true;
+    }
+}
+
+pub fn debugger(args: &mut Arguments, ast: Vec<ASTNode>) -> bool {
+    let Some(mut dbg) = Debugger::new(args, ast) else {
+        return false;
+    };
+    println!(
+        "Burlap v{} debugger, type \"help\" for help",
+        env!("CARGO_PKG_VERSION")
+    );
+    let mut symbols = dbg.vm.get_symbols(true);
+    symbols.extend([
+        "run", "continue", "step", "next", "finish", "break", "delete",
+        "info", "locals", "globals", "backtrace", "print", "list", "help",
+        "quit",
+    ].iter().map(|x| x.to_string()));
+    let mut rl = LineEditor::new(args, symbols);
+    let mut last = String::new();
+    loop {
+        let line = match rl.readline("(burlap) ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(_) => break,
+        };
+        // Empty lines repeat the last command
+        let line = if line.trim().is_empty() { last.clone() } else { line };
+        if !dbg.command(&line) {
+            break;
+        }
+        last = line;
+    }
+    rl.save();
+    return true;
+}
@@ -0,0 +1,121 @@
+use std::fmt::Write;
+
+use crate::bytecode::{decode, operand_size, read_operand};
+use crate::common::{get_line, Stream};
+use crate::compiler::Program;
+use crate::value::{Value, View};
+use crate::vm::Opcode;
+
+use rustc_hash::FxHashMap;
+
+// Shows a constant, strings are quoted
+fn show_const(val: &Value) -> String {
+    if let View::Str(s) = val.view() {
+        return format!("{:?}", s);
+    }
+    return val.to_string().unwrap_or_else(|_| format!("{:?}", val));
+}
+
+// Finds where functions start, using `functis` and LFN
+fn find_functis(program: &Program) -> FxHashMap<usize, String> {
+    let mut ret: FxHashMap<usize, String> = program.functis.iter()
+        .map(|(name, (pos, args))| (*pos, format!("{}({} args)", name, args)))
+        .collect();
+    let ops = &program.ops;
+    // The last constants, LFN takes the arg count
+    let mut consts: Vec<&Value> = vec![];
+    let mut at = 0;
+    while at < ops.len() {
+        let Some(op) = decode(ops[at]) else {
+            at += 1;
+            continue;
+        };
+        let size = operand_size(op);
+        if at + size >= ops.len() {
+            break;
+        }
+        match op {
+            Opcode::PUSH | Opcode::PUSH3 => {
+                let index = read_operand(ops, at, size);
+                if let Some(val) = program.consts.get(index) {
+                    consts.push(val);
+                }
+            },
+            // Functions start after the JMPU that skips over them
+            Opcode::LFN => {
+                let args = consts.last().map(|val| val.view());
+                if let Some(View::Int(args)) = args {
+                    ret.insert(at + 5, format!("<anonymous>({} args)", args));
+                }
+            },
+            _ => {},
+        }
+        at += size + 1;
+    }
+    return ret;
+}
+
+// Turns bytecode back into readable instructions, with the source lines
+pub fn disasm(program: &Program) -> String {
+    let functis = find_functis(program);
+    let ops = &program.ops;
+    let mut ret = String::new();
+    let mut at = 0;
+    while at < ops.len() {
+        // Source lines
+        if program.is_line_start(at as u32) {
+            let (line, file) = program.get_info(at as u32);
+            if !ret.is_empty() {
+                ret += "\n";
+            }
+            writeln!(ret, "; {}:{}", file, line).unwrap();
+            let src = get_line(&Stream{
+                name: file, line: line as usize, at: 0, rat: 0, size: 0
+            });
+            if let Some(src) = src {
+                writeln!(ret, ";    {} | {}", line, src.trim_end()).unwrap();
+            }
+        }
+        // Functions
+        if let Some(functi) = functis.get(&at) {
+            writeln!(ret, "functi {}:", functi).unwrap();
+        }
+        // The instruction
+        let Some(op) = decode(ops[at]) else {
+            writeln!(ret, "{:>6}  ?? ({})", at, ops[at]).unwrap();
+            at += 1;
+            continue;
+        };
+        let size = operand_size(op);
+        if at + size >= ops.len() {
+            writeln!(ret, "{:>6}  {:?} (truncated)", at, op).unwrap();
+            break;
+        }
+        let operand = read_operand(ops, at, size);
+        let args = match op {
+            Opcode::PUSH | Opcode::PUSH3 => format!(
+                "{:<8}; {}", operand, program.consts.get(operand)
+                    .map(show_const).unwrap_or("<invalid>".to_string())
+            ),
+            // Jumps are relative to the end of the instruction
+            Opcode::JMPU | Opcode::JMPNT | Opcode::TRY =>
+                format!("{:<8}; -> {}", operand, at + 3 + operand),
+            Opcode::JMPB | Opcode::TCO => format!(
+                "{:<8}; -> {}", operand,
+                (at + 3).checked_sub(operand).map(|x| x.to_string())
+                    .unwrap_or("<invalid>".to_string())
+            ),
+            Opcode::PLV | Opcode::SLV | Opcode::SKLV | Opcode::ALV => {
+                operand.to_string()
+            },
+            Opcode::CALLD => format!(
+                "{:<8}; {}", operand, program.functi_name(operand)
+            ),
+            _ => "".to_string(),
+        };
+        let line = format!("{:>6}  {:<6}{}", at, format!("{:?}", op), args);
+        writeln!(ret, "{}", line.trim_end()).unwrap();
+        at += size + 1;
+    }
+    return ret;
+}
@@ -0,0 +1,202 @@
+use crate::common::IMPOSSIBLE_STATE;
+use crate::lexer::{lex, Token, TokenType};
+use TokenType::*;
+
+// The width of one indent level
+const INDENT: &str = "    ";
+
+// Checks if a token is a comment (whitespace is also Skipped)
+fn is_comment(token: &Token) -> bool {
+    token.token == Skipped && token.str.starts_with('#')
+}
+
+// Checks if a token can end a value (so a following '-' is binary)
+fn ends_value(token: &TokenType) -> bool {
+    matches!(token,
+        Identifier(_) | Str(_) | Int(_) | Float(_) | Bool(_) | None | Byte(_)
+        | Rparan | Rbracket
+    )
+}
+
+// Checks if a token opens/closes a nesting level
+fn is_open(token: &TokenType) -> bool {
+    matches!(token, Lparan | Lbracket | Lbrace)
+}
+fn is_close(token: &TokenType) -> bool {
+    matches!(token, Rparan | Rbracket | Rbrace)
+}
+
+// Decides if a space goes between two tokens on the same line
+// `before` is the token before `prev`, used to find unary minuses
+fn needs_space(
+    before: Option<&TokenType>, prev: &Token, cur: &Token
+) -> bool {
+    // Trailing comments
+    if is_comment(cur) {
+        return true;
+    }
+    let (prev, cur) = (&prev.token, &cur.token);
+    match (prev, cur) {
+        // `{}` and `{ x; }`
+        (Lbrace, Rbrace) => false,
+        (Lbrace, _) | (_, Rbrace) => true,
+        // Insides of parens and brackets
+        (Lparan | Lbracket, _) => false,
+        (_, Rparan | Rbracket | Comma | Semicolon | Colon) => false,
+        (Comma | Semicolon | Colon, _) => true,
+        // Module items, `util.init`
+        (Dot, _) | (_, Dot) => false,
+        // Calls (`print(`) and indexes (`list[`)
+        (Identifier(_) | Rparan | Rbracket | Import | Func(_), Lparan) => false,
+        (Identifier(_) | Rparan | Rbracket | Str(_), Lbracket) => false,
+        // Unary operators
+        (Not | PlusPlus | MinusMinus, _) => false,
+        (Minus, _) => before.is_some_and(ends_value),
+        _ => true,
+    }
+}
+
+// Splits the tokens into lines, without whitespace
+fn split_lines(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    let mut lines: Vec<Vec<Token>> = vec![vec![]];
+    for token in tokens {
+        match token.token {
+            Newline => lines.push(vec![]),
+            Eof => {},
+            Skipped if !is_comment(&token) => {},
+            _ => lines.last_mut().unwrap().push(token),
+        }
+    }
+    return lines;
+}
+
+// Moves `{`, `else`, and `catch` up onto the line before them
+fn join_lines(lines: Vec<Vec<Token>>) -> Vec<Vec<Token>> {
+    let mut ret: Vec<Vec<Token>> = vec![];
+    for line in lines {
+        // Find the line to join to, skipping blank lines
+        let target = ret.iter().rposition(|l| !l.is_empty());
+        let last = target.map(|t| ret[t].last().unwrap());
+        let joins = match (line.first().map(|t| &t.token), last) {
+            // Comments can't be joined onto
+            (_, Some(last)) if is_comment(last) => false,
+            // `else` and `catch` after `}`
+            (Some(Else | Catch), Some(last)) => last.token == Rbrace,
+            // `{` after `if x`, `functi f()`, etc.., not after a statement
+            (Some(Lbrace), Some(last)) => !matches!(
+                last.token, Semicolon | Lbrace | Rbrace
+            ),
+            _ => false,
+        };
+        if let (true, Some(target)) = (joins, target) {
+            ret.truncate(target + 1);
+            ret[target].extend(line);
+            continue;
+        }
+        ret.push(line);
+    }
+    return ret;
+}
+
+// Puts braces that open/close blocks from other lines on their own line
+fn split_blocks(lines: Vec<Vec<Token>>) -> Vec<Vec<Token>> {
+    let mut ret: Vec<Vec<Token>> = vec![];
+    for line in lines {
+        // Find the braces that aren't matched on this line
+        let mut opens: Vec<usize> = vec![];
+        let mut closes: Vec<usize> = vec![];
+        for (at, token) in line.iter().enumerate() {
+            if token.token == Lbrace {
+                opens.push(at);
+            } else if token.token == Rbrace && opens.pop().is_none() {
+                closes.push(at);
+            }
+        }
+        // After a `{`, unless it ends the line or only a comment follows
+        let rest = |at: usize| &line[at + 1..];
+        opens.retain(|&at| match rest(at) {
+            [] => false,
+            [token] => !is_comment(token),
+            _ => true,
+        });
+        let mut cur: Vec<Token> = vec![];
+        for (at, token) in line.into_iter().enumerate() {
+            // Before a `}`, unless it's already first
+            if closes.contains(&at) && !cur.is_empty() {
+                ret.push(std::mem::take(&mut cur));
+            }
+            cur.push(token);
+            if opens.contains(&at) {
+                ret.push(std::mem::take(&mut cur));
+            }
+        }
+        ret.push(cur);
+    }
+    return ret;
+}
+
+// Formats sack source into the canonical layout
+pub fn format(src: &str, name: String, color: bool) -> Option<String> {
+    // Lex with errors first, so broken files are reported and left alone
+    lex(src, name.clone(), true, color)?;
+    // The REPL mode keeps comments and newlines
+    let tokens = lex(src, name, false, color)?;
+    let lines = split_blocks(join_lines(split_lines(tokens)));
+
+    let mut ret = String::new();
+    // Nesting of (), [], and {}
+    let mut depth: usize = 0;
+    // Blank lines are only kept between two lines of code
+    let mut blank = false;
+    let mut after_open = true;
+    for line in lines {
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        // Blank line
+        let closes = line.iter().take_while(|t| is_close(&t.token)).count();
+        if blank && !after_open && closes == 0 {
+            ret += "\n";
+        }
+        blank = false;
+        // Indent
+        ret += &INDENT.repeat(depth.saturating_sub(closes));
+        // Tokens
+        let mut before: Option<&TokenType> = Option::None;
+        for (at, token) in line.iter().enumerate() {
+            if at != 0 && needs_space(before, &line[at - 1], token) {
+                ret += " ";
+            }
+            if at != 0 {
+                before = Some(&line[at - 1].token);
+            }
+            if is_comment(token) {
+                ret += token.str.trim_end();
+            } else {
+                ret += &token.str;
+            }
+            // Track nesting
+            if is_open(&token.token) {
+                depth += 1;
+            } else if is_close(&token.token) {
+                depth = depth.saturating_sub(1);
+            }
+        }
+        after_open = is_open(&line.last().unwrap().token);
+        ret += "\n";
+    }
+    return Some(ret);
+}
+
+// Checks that formatting didn't change anything but the layout
+pub fn same_tokens(a: &str, b: &str) -> bool {
+    let significant = |src: &str| -> Vec<TokenType> {
+        lex(src, "".to_string(), false, false)
+            .expect(IMPOSSIBLE_STATE).into_iter()
+            .map(|t| t.token)
+            .filter(|t| !matches!(t, Skipped | Newline))
+            .collect()
+    };
+    return significant(a) == significant(b);
+}
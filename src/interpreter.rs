@@ -0,0 +1,292 @@
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::{Arguments, to_ast};
+use crate::common::{capture_errs, take_errs, ErrType};
+use crate::compiler::compile;
+use crate::native::NativeFn;
+use crate::value::{Value, View};
+use crate::vm::{get_traceback, step, Vm};
+
+// What went wrong
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    // Lexing, parsing, and compiling
+    Syntax,
+    Runtime,
+    // Files that couldn't be read
+    Io,
+}
+
+// One call in a traceback
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    // `f()`, or `<top level>`
+    pub name: String,
+    pub file: String,
+    pub line: u32,
+}
+
+// An error, returned instead of printed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    // Where it is, `line` is 0 if it's unknown
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    // Hints and notes about the error
+    pub notes: Vec<String>,
+    // The calls the error happened in, innermost last (runtime errors only)
+    pub traceback: Vec<Frame>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, "{}", self.message);
+        }
+        return write!(
+            f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message
+        );
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    fn new(kind: ErrorKind, message: String) -> Error {
+        return Error {
+            kind, message, file: "".to_string(), line: 0, column: 0,
+            notes: vec![], traceback: vec![],
+        };
+    }
+}
+
+// Runs sack code from Rust
+// Every eval shares the same globals and functions, like the REPL
+pub struct Interpreter {
+    vm: Vm,
+    args: Arguments,
+    // Files that have been run, their globals are mangled with their name
+    // `<eval>` is first, so its globals are found first
+    files: Vec<String>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        return Self::with_extensions(&[]);
+    }
+
+    // Enables extensions, `["auto-none"]` is like `--use-auto-none`
+    pub fn with_extensions(extensions: &[&str]) -> Interpreter {
+        let mut args = Arguments::new();
+        args.name = "<eval>".to_string();
+        args.extensions = extensions.iter().map(|e| e.to_string()).collect();
+        // Keeps the value of the last expression
+        args.is_repl = true;
+        return Interpreter {
+            vm: Vm::new(args.clone()), args, files: vec!["<eval>".to_string()],
+        };
+    }
+
+    // Adds a native function, it takes any number of args if `arg_num` is None
+    pub fn register(
+        &mut self, name: &str, arg_num: Option<usize>,
+        functi: impl Fn(Vec<Value>) -> Result<Value, String> + 'static
+    ) {
+        let functi_name = name.to_string();
+        self.vm.add_functie(name, Rc::new(move |vm, args| {
+            if let Some(arg_num) = arg_num {
+                if args.len() != arg_num {
+                    vm.bad_args(&functi_name, args.len(), arg_num)?;
+                }
+            }
+            return functi(args);
+        }));
+    }
+
+    // Adds a native function with typed args (`|a: i32, b: String| ...`)
+    // The args are checked and converted, and the return value converted
+    pub fn register_fn<Args>(&mut self, name: &str, functi: impl NativeFn<Args>) {
+        self.vm.add_functie(name, functi.into_functie(name));
+    }
+
+    // Runs source code, returning the value of the last expression
+    pub fn eval(&mut self, src: &str) -> Result<Value, Error> {
+        return self.run_source(src.to_string() + ";", "<eval>");
+    }
+
+    // Runs a file, imports are relative to it
+    pub fn eval_file(&mut self, path: &str) -> Result<Value, Error> {
+        let src = read_to_string(path).map_err(|err| Error::new(
+            ErrorKind::Io, format!("failed to open {}: {}", path, err)
+        ))?;
+        if !self.files.iter().any(|f| f == path) {
+            self.files.push(path.to_string());
+        }
+        return self.run_source(src, path);
+    }
+
+    // Gets a global, globals from `eval` are checked before ones from files
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        for file in &self.files {
+            let name = format!("{}::{}", file, name);
+            if let Ok(val) = self.vm.get_global(&name) {
+                return Some(val);
+            }
+        }
+        return None;
+    }
+
+    // Sets a global, making it if it doesn't exist
+    // Once it exists, `eval` code can use it
+    pub fn set_global(&mut self, name: &str, val: Value) {
+        for file in &self.files {
+            let name = format!("{}::{}", file, name);
+            if self.vm.set_global(&name, val.clone()) {
+                return;
+            }
+        }
+        let name = format!("<eval>::{}", name);
+        self.vm.make_var(&name, val).expect("globals can always be made");
+        self.vm.program.add_global(&name);
+    }
+
+    // Calls a sack function (or builtin) by name
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let Some(functi) = self.vm.get_functi(name)
+            .or_else(|| self.get_global(name))
+        else {
+            return Err(Error::new(
+                ErrorKind::Runtime, format!("no function called \"{}\"", name)
+            ));
+        };
+        self.vm.use_decimals();
+        let state = self.vm.save_state();
+        let depth = self.vm.get_call_frames().len();
+        // Return to the end of the program, the VM pops args in reverse
+        self.vm.at = self.vm.program.ops.len().saturating_sub(1);
+        let args: Vec<Value> = args.into_iter().rev().collect();
+        if let Err(msg) = self.vm.call_value(&functi, &args) {
+            self.vm.restore_state(&state);
+            return Err(self.definition_err(&functi, msg));
+        }
+        // `step` would skip the first op of the function
+        self.vm.jump = false;
+        while self.vm.get_call_frames().len() > depth {
+            if let Err(msg) = step(&mut self.vm) {
+                let err = self.runtime_err(msg);
+                self.vm.restore_state(&state);
+                return Err(err);
+            }
+        }
+        let ret = self.vm.pop();
+        self.vm.restore_state(&state);
+        return Ok(ret);
+    }
+
+    fn run_source(&mut self, src: String, name: &str) -> Result<Value, Error> {
+        self.args.source = src;
+        self.args.name = name.to_string();
+        self.args.path = PathBuf::from(name);
+        // Compile
+        let start = self.vm.program.ops.len();
+        capture_errs();
+        let compiled = to_ast(&mut self.args).is_some_and(|ast| {
+            compile(ast, &mut self.args, &mut self.vm.program)
+        });
+        let diagnostics = take_errs();
+        if !compiled {
+            let mut errors = vec![];
+            for diagnostic in diagnostics {
+                let notes = match diagnostic.errtype {
+                    ErrType::Err => {
+                        errors.push(Error {
+                            kind: ErrorKind::Syntax, message: diagnostic.msg,
+                            file: diagnostic.file,
+                            line: diagnostic.line as u32,
+                            column: diagnostic.at as u32,
+                            notes: vec![], traceback: vec![],
+                        });
+                        continue;
+                    },
+                    ErrType::Hint | ErrType::Note => diagnostic.msg,
+                    ErrType::Warn => continue,
+                };
+                if let Some(err) = errors.last_mut() {
+                    err.notes.push(notes);
+                }
+            }
+            // The first error is the one that matters
+            return Err(errors.into_iter().next().unwrap_or(Error::new(
+                ErrorKind::Syntax, "failed to compile".to_string()
+            )));
+        }
+        if self.vm.program.ops.len() == start {
+            return Ok(Value::None);
+        }
+        // Run
+        self.vm.use_decimals();
+        self.vm.at = start;
+        let state = self.vm.save_state();
+        let stack_len = self.vm.stack_len();
+        loop {
+            match step(&mut self.vm) {
+                Ok(true) => {},
+                Ok(false) => break,
+                Err(msg) => {
+                    let err = self.runtime_err(msg);
+                    self.vm.restore_state(&state);
+                    self.vm.at = self.vm.program.ops.len() - 1;
+                    return Err(err);
+                }
+            }
+        }
+        // The last expression is left on the stack
+        let ret = if self.vm.stack_len() > stack_len {
+            self.vm.pop()
+        } else {
+            Value::None
+        };
+        self.vm.restore_state(&state);
+        self.vm.at = self.vm.program.ops.len() - 1;
+        return Ok(ret);
+    }
+
+    // Makes an error at a function's definition, for bad calls from Rust
+    fn definition_err(&self, functi: &Value, message: String) -> Error {
+        let mut err = Error::new(ErrorKind::Runtime, message);
+        // Builtins aren't defined anywhere
+        let View::Functi(info) = functi.view() else {
+            return err;
+        };
+        if let Some(pos) = info.pos {
+            // The jump over the body is on the definition's line
+            (err.line, err.file) = self.vm.program.get_info(pos as u32 - 1);
+        }
+        return err;
+    }
+
+    // Makes an error at the current op
+    fn runtime_err(&self, message: String) -> Error {
+        let traceback: Vec<Frame> = get_traceback(&self.vm).into_iter()
+            .map(|(name, line, file)| Frame { name, file, line }).collect();
+        let last = traceback.last().expect("there's always a frame");
+        let column = self.vm.program.get_span(self.vm.at as u32)
+            .map_or(0, |span| span.at);
+        return Error {
+            kind: ErrorKind::Runtime, message, file: last.file.clone(),
+            line: last.line, column, notes: vec![], traceback,
+        };
+    }
+}
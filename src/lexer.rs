@@ -1,4 +1,5 @@
 use logos::Logos;
+use num_bigint::BigInt;
 
 use crate::common::{err, ErrType, Stream};
 
@@ -24,9 +25,9 @@ pub enum TokenType {
     )]
     Str(String),
     #[regex(r"(?&numbers)", |lex| lex.slice().parse().ok())]
-    Int(i32),
+    Int(BigInt),
     #[regex(r"(?&numbers)\.(?&numbers)", |lex| lex.slice().parse().ok())]
-    Float(f32),
+    Float(f64),
     #[regex("(true)|(false)", |lex| lex.slice() == "true")]
     Bool(bool),
     #[token("none")]
@@ -58,6 +59,8 @@ pub enum TokenType {
     Semicolon,
     #[token(":")]
     Colon,
+    #[token(".")]
+    Dot,
     #[token(",")]
     Comma,
     #[token("=")]
@@ -71,8 +74,14 @@ pub enum TokenType {
     Func(bool),
     #[token("let")]
     Let,
+    #[token("private")]
+    Private,
     #[token("return")]
     Return,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
     #[token("in")]
     In,
     #[token("if")]
@@ -85,6 +94,12 @@ pub enum TokenType {
     While,
     #[token("import")]
     Import,
+    #[token("as")]
+    As,
+    #[token("try")]
+    Try,
+    #[token("catch")]
+    Catch,
     // Basic operators
     #[token("+")]
     Plus,
@@ -146,9 +161,9 @@ impl std::fmt::Debug for Token {
 }
 
 pub fn lex(
-    src: &String, name: String, print_err: bool, color: bool
+    src: &str, name: String, print_err: bool, color: bool
 ) -> Option<Vec<Token>> {
-    let mut lex = TokenType::lexer(src.as_str());
+    let mut lex = TokenType::lexer(src);
     let mut ret: Vec<Token> = vec![];
     // Lines
     let lines = src.lines().collect::<Vec<&str>>();
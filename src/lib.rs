@@ -0,0 +1,95 @@
+#![allow(clippy::needless_return, clippy::print_literal)]
+#[macro_use] extern crate impl_ops;
+use std::path::PathBuf;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+mod bytecode;
+mod capi;
+#[cfg(feature = "cffi")]
+mod cffi;
+// Only public for the binary
+#[doc(hidden)]
+pub mod cli;
+mod common;
+mod compiler;
+mod debugger;
+mod disasm;
+mod formatter;
+mod interpreter;
+mod lexer;
+mod native;
+mod parser;
+mod repl;
+mod stdlib;
+mod value;
+mod vm;
+
+use crate::lexer::lex;
+use crate::parser::{parse, ASTNode, Module};
+
+// The embedding API
+pub use crate::interpreter::{Error, ErrorKind, Frame, Interpreter};
+pub use crate::native::{FromValue, IntoValue, NativeFn, NativeReturn};
+pub use crate::value::{FileInfo, FunctiInfo, Value, View};
+
+#[derive(Clone)]
+pub(crate) struct Arguments {
+    source: String,
+    name: String,
+    path: PathBuf,
+    is_debug: bool,
+    is_debugger: bool,
+    is_repl: bool,
+    format: bool,
+    check: bool,
+    compile: bool,
+    disasm: bool,
+    output: String,
+    extensions: Vec<String>,
+    program_args: Vec<String>,
+    // Extra directories to look for imports in (`-I dir`)
+    import_dirs: Vec<PathBuf>,
+    // Import aliases and private functions, by mangled name
+    modules: FxHashMap<String, Module>,
+    private: FxHashSet<String>,
+    // Imported files, and the files being imported, by full path
+    imported: FxHashMap<String, Module>,
+    import_chain: Vec<(String, String)>
+}
+
+impl Default for Arguments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Arguments {
+    pub fn new() -> Arguments {
+        Arguments {
+            source: "".to_string(), is_debug: false, is_debugger: false,
+            is_repl: true, extensions: vec!["color".to_string()],
+            name: "<stdin>".to_string(), format: false, check: false,
+            compile: false, disasm: false, output: "".to_string(),
+            program_args: vec![], path: PathBuf::from("."), import_dirs: vec![],
+            modules: FxHashMap::default(), private: FxHashSet::default(),
+            imported: FxHashMap::default(), import_chain: vec![]
+        }
+    }
+}
+
+pub(crate) fn to_ast(args: &mut Arguments) -> Option<Vec<ASTNode>> {
+    // Lex
+    let tokens = lex(
+        &args.source, args.name.clone(), true,
+        args.extensions.contains(&"color".to_string()),
+    )?;
+    args.source = "".to_string();
+    // Parse
+    let ast = parse(tokens, args)?;
+    if args.is_debug {
+        // Debug print ast
+        println!("Ast: {:?}", ast);
+    }
+    return Some(ast);
+}
+
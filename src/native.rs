@@ -0,0 +1,285 @@
+// Native functions, Rust functions that sack can call
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+
+use crate::common::IMPOSSIBLE_STATE;
+use crate::value::{Value, View};
+use crate::vm::{Functie, Vm};
+
+// Converts a sack value into a Rust type, used for args
+// Errors say what's wrong with the value (`must be a list`)
+pub trait FromValue: Sized {
+    // The sack type, for errors
+    const TYPE: &'static str;
+    fn from_value(val: Value) -> Result<Self, String>;
+}
+
+// The error for a value of the wrong type
+fn wrong_type<T: FromValue>() -> String {
+    return format!("must be a {}", T::TYPE);
+}
+
+// Converts a Rust type into a sack value, used for return values
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl FromValue for Value {
+    const TYPE: &'static str = "value";
+    fn from_value(val: Value) -> Result<Self, String> {
+        return Ok(val);
+    }
+}
+
+impl FromValue for i64 {
+    const TYPE: &'static str = "number";
+    fn from_value(val: Value) -> Result<Self, String> {
+        let View::Int(i) = val.view() else {
+            return Err(wrong_type::<Self>());
+        };
+        return Ok(i);
+    }
+}
+
+// Any number, big or not
+impl FromValue for BigInt {
+    const TYPE: &'static str = "number";
+    fn from_value(val: Value) -> Result<Self, String> {
+        return match val.view() {
+            View::Int(_) | View::BigInt(_) => Ok(val.to_big()),
+            _ => Err(wrong_type::<Self>()),
+        };
+    }
+}
+
+impl FromValue for i32 {
+    const TYPE: &'static str = "number";
+    fn from_value(val: Value) -> Result<Self, String> {
+        let View::Int(i) = val.view() else {
+            return Err(wrong_type::<Self>());
+        };
+        return i32::try_from(i)
+            .map_err(|_| "must fit in 32 bits".to_string());
+    }
+}
+
+// Numbers are turned into decimals
+impl FromValue for f64 {
+    const TYPE: &'static str = "decimal";
+    fn from_value(val: Value) -> Result<Self, String> {
+        return match val.view() {
+            View::Float(f) => Ok(f),
+            View::Int(_) | View::BigInt(_) => Ok(val.to_float()),
+            _ => Err(wrong_type::<Self>()),
+        };
+    }
+}
+
+impl FromValue for f32 {
+    const TYPE: &'static str = "decimal";
+    fn from_value(val: Value) -> Result<Self, String> {
+        return f64::from_value(val).map(|f| f as f32);
+    }
+}
+
+impl FromValue for bool {
+    const TYPE: &'static str = "bool";
+    fn from_value(val: Value) -> Result<Self, String> {
+        let View::Bool(b) = val.view() else {
+            return Err(wrong_type::<Self>());
+        };
+        return Ok(b);
+    }
+}
+
+impl FromValue for u8 {
+    const TYPE: &'static str = "byte";
+    fn from_value(val: Value) -> Result<Self, String> {
+        let View::Byte(b) = val.view() else {
+            return Err(wrong_type::<Self>());
+        };
+        return Ok(b);
+    }
+}
+
+impl FromValue for String {
+    const TYPE: &'static str = "string";
+    fn from_value(val: Value) -> Result<Self, String> {
+        let Some(s) = val.into_rc::<String>() else {
+            return Err(wrong_type::<Self>());
+        };
+        return Ok(Rc::unwrap_or_clone(s));
+    }
+}
+
+// Keys are dropped, errors say which element is wrong
+impl<T: FromValue> FromValue for Vec<T> {
+    const TYPE: &'static str = "list";
+    fn from_value(val: Value) -> Result<Self, String> {
+        let items: Vec<Value> = match val.view() {
+            View::FastList(_) => {
+                Rc::unwrap_or_clone(val.into_rc().expect(IMPOSSIBLE_STATE))
+            },
+            View::List(l) => l.values().cloned().collect(),
+            _ => return Err(wrong_type::<Self>()),
+        };
+        return items.into_iter().enumerate().map(|(at, item)| {
+            T::from_value(item).map_err(|err| format!("element {} {}", at, err))
+        }).collect();
+    }
+}
+
+// `none` is None
+impl<T: FromValue> FromValue for Option<T> {
+    const TYPE: &'static str = T::TYPE;
+    fn from_value(val: Value) -> Result<Self, String> {
+        if val == Value::None {
+            return Ok(None);
+        }
+        return T::from_value(val).map(Some);
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        return self;
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        return Value::None;
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        return Value::Int(self);
+    }
+}
+
+impl IntoValue for BigInt {
+    fn into_value(self) -> Value {
+        return Value::from_big(self);
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        return Value::Int(self as i64);
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        return Value::decimal(self);
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        return Value::Float(self as f64);
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        return Value::Bool(self);
+    }
+}
+
+impl IntoValue for u8 {
+    fn into_value(self) -> Value {
+        return Value::Byte(self);
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        return Value::str(self);
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        return Value::str(self);
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        return Value::fast_list(self.into_iter().map(T::into_value).collect());
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        return self.map_or(Value::None, T::into_value);
+    }
+}
+
+// What native functions can return, errors are runtime errors
+pub trait NativeReturn {
+    fn into_result(self) -> Result<Value, String>;
+}
+
+impl<T: IntoValue> NativeReturn for T {
+    fn into_result(self) -> Result<Value, String> {
+        return Ok(self.into_value());
+    }
+}
+
+impl<T: IntoValue> NativeReturn for Result<T, String> {
+    fn into_result(self) -> Result<Value, String> {
+        return self.map(T::into_value);
+    }
+}
+
+// Rust functions that can be made into functies, `Args` is a tuple of args
+pub trait NativeFn<Args> {
+    fn into_functie(self, name: &str) -> Functie;
+}
+
+// Gets the arg or makes an error like `len() argument 1 must be a list`
+fn get_arg<T: FromValue>(
+    name: &str, at: usize, args: &mut impl Iterator<Item = Value>
+) -> Result<T, String> {
+    // The number of args is checked first
+    let val = args.next().expect(IMPOSSIBLE_STATE);
+    return T::from_value(val).map_err(|err| format!(
+        "{}() argument {} {}", name, at, err
+    ));
+}
+
+macro_rules! impl_native_fn {
+    ($arg_num:expr; $($arg:ident $val:ident $at:expr),*) => {
+        impl<F, R, $($arg),*> NativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: NativeReturn,
+            $($arg: FromValue),*
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn into_functie(self, name: &str) -> Functie {
+                let name = name.to_string();
+                return Rc::new(move |vm: &mut Vm, args: Vec<Value>| {
+                    if args.len() != $arg_num {
+                        vm.bad_args(&name, args.len(), $arg_num)?;
+                    }
+                    let mut args = args.into_iter();
+                    $(let $val: $arg = get_arg(&name, $at, &mut args)?;)*
+                    return self($($val),*).into_result();
+                });
+            }
+        }
+    };
+}
+
+impl_native_fn!(0;);
+impl_native_fn!(1; A a 1);
+impl_native_fn!(2; A a 1, B b 2);
+impl_native_fn!(3; A a 1, B b 2, C c 3);
+impl_native_fn!(4; A a 1, B b 2, C c 3, D d 4);
+impl_native_fn!(5; A a 1, B b 2, C c 3, D d 4, E e 5);
+impl_native_fn!(6; A a 1, B b 2, C c 3, D d 4, E e 5, G g 6);
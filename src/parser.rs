@@ -1,7 +1,15 @@
-use std::fs::read_to_string;
+use std::env;
+use std::fs::{canonicalize, read_to_string};
+use std::path::PathBuf;
+use home::home_dir;
+use num_bigint::BigInt;
+use rustc_hash::FxHashSet;
 use crate::{Arguments, to_ast};
-use crate::common::{err, ErrType, IMPOSSIBLE_STATE};
+use crate::common::{
+    err, print_err, ErrType, Span, Stream, IMPOSSIBLE_STATE
+};
 use crate::lexer::{Token, TokenType};
+use crate::stdlib::{get_std_module, std_module_names};
 use TokenType::*;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -10,53 +18,80 @@ pub enum ASTNode {
     // String, ("Example")
     StringExpr(String),
     // Number, (47)
-    NumberExpr(i32),
+    NumberExpr(BigInt),
     // Float, (3.14)
-    DecimalExpr(f32),
+    DecimalExpr(f64),
     // Bool, (false)
     BoolExpr(bool),
     // None
     NoneExpr,
     // Byte, (0b00000000)
     ByteExpr(u8),
+    // Operations that can fail at runtime keep their span for errors
     // Var, (x)
-    VarExpr(String),
-    // Call, (print, [Number(7)])
-    CallExpr(String, Vec<ASTNode>),
+    VarExpr(String, Span),
+    // Call, (Var(print), [Number(7)])
+    CallExpr(Box<ASTNode>, Vec<ASTNode>, Span),
     // Index, (mylist, Number(7))
-    IndexExpr(Box<ASTNode>, Box<ASTNode>),
+    IndexExpr(Box<ASTNode>, Box<ASTNode>, Span),
     // Unary, (Minus, Number(1))
-    UnaryExpr(TokenType, Box<ASTNode>),
+    UnaryExpr(TokenType, Box<ASTNode>, Span),
     // Binop, (Number(2), "+", Number(2))
-    BinopExpr(Box<ASTNode>, TokenType, Box<ASTNode>),
-    // List (keys["1", "e"], values[node, node], fast = false)
-    ListExpr(Vec<String>, Vec<ASTNode>, bool),
+    BinopExpr(Box<ASTNode>, TokenType, Box<ASTNode>, Span),
+    // List (keys[None, String("e")], values[node, node], fast = false)
+    // Items without keys have a none key
+    ListExpr(Vec<ASTNode>, Vec<ASTNode>, bool),
+    // Anonymous function, ([a, b], Body(...))
+    FunctiExpr(Vec<String>, Box<ASTNode>),
 
     // Statements
     // Body, ([Call(Var(print), [String("Hello World")])])
     BodyStmt(Vec<ASTNode>),
-    // Function, (foobar, [a, b, c], Body(...))
-    FunctiStmt(String, Vec<String>, Box<ASTNode>),
+    // Function, (foobar, [a, b, c], Body(...)), the span is the name's
+    FunctiStmt(String, Vec<String>, Box<ASTNode>, Span),
     // If/else if, (Binop(x == 1), Body(trueBody), Body(falseBody or nop))
     IfStmt(Box<ASTNode>, Box<ASTNode>, Box<ASTNode>),
     // Let, (x, 47)
     LetStmt(String, Box<ASTNode>),
     // Return, ("Return Val")
     ReturnStmt(Box<ASTNode>),
+    // Break, leaves the innermost loop
+    BreakStmt,
+    // Continue, goes to the next iteration of the innermost loop
+    ContinueStmt,
     // Iter loop, (i, range(0, 100), Body(...))
     LoopStmt(String, Box<ASTNode>, Box<ASTNode>),
     // While loop, (6 > i, Body(...))
     WhileStmt(Box<ASTNode>, Box<ASTNode>),
-    // ImportStmt, used for the file table as the parser handles imports
-    ImportStmt(),
+    // Try/catch, (Body(...), e, Body(...))
+    TryStmt(Box<ASTNode>, String, Box<ASTNode>),
+    // ImportStmt, (filename), used for the file table as the parser handles
+    // imports
+    ImportStmt(String),
     // EndImportStmt, (filename), used for marking the end of the import
     EndImportStmt(String),
+    // LineStmt, (line), used for the line table, marks the next statement
+    LineStmt(u32),
+    // Private, (Let(x, 47)), a global that can't be used from other files
+    PrivateStmt(Box<ASTNode>),
 
     // Special
     // Nop, does nothing
     Nop,
 }
 
+// An aliased import, `import("util") as util;`
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub file: String,
+    // Functions are named `prefix + name`, it's `file:` if they were aliased
+    pub prefix: String,
+    pub functis: Vec<String>,
+    pub globals: Vec<String>,
+    // Things that can't be used outside of the file
+    pub private: Vec<String>,
+}
+
 // Parser state
 struct Parser {
     tokens: Vec<Token>,
@@ -64,6 +99,7 @@ struct Parser {
     args: Arguments,
     at: usize,
     in_func: bool,
+    in_loop: bool,
     has_err: bool,
     name: String
 }
@@ -79,6 +115,20 @@ impl Parser {
         self.at += 1;
         self.current()
     }
+
+    // Position of the current token
+    fn stream(&self) -> Stream {
+        self.tokens[self.at].stream.clone()
+    }
+
+    // Span from `start` to the end of the last eaten token
+    fn span(&self, start: &Stream) -> Span {
+        let end = &self.tokens[self.at - 1].stream;
+        Span {
+            line: start.line as u32, at: start.at as u32,
+            size: (end.rat + end.size).saturating_sub(start.rat) as u32,
+        }
+    }
 }
 
 // Error macro
@@ -118,75 +168,80 @@ macro_rules! eat_semicolon {
 }
 
 // Expressions
-// Call
-fn parse_call(parser: &mut Parser) -> Option<ASTNode> {
-    // A function can't return a function so no need for a loop
-    if let Identifier(_) = parser.current() {} else {
-        // Can't call anything but identifiers
-        return parse_base_expr(parser);
-    }
-    // Eat
-    let mut ret = parse_base_expr(parser)?;
-    let name: String;
-    if let ASTNode::VarExpr(n) = &ret {
-        name = n.clone().split("::").nth(1).unwrap().to_string();
-    } else {
-        return Some(ret);
-    }
-    // Parse call
+// Call args, `(a, b)`
+fn parse_args(parser: &mut Parser) -> Option<Vec<ASTNode>> {
+    eat!(parser, Lparan, IMPOSSIBLE_STATE)?;
     let mut args: Vec<ASTNode> = vec![];
-    if let Lparan = parser.current() {
-        parser.next();
-        loop {
-            if let Rparan = parser.current() {
-                break;
-            }
-            args.push(parse_expr(parser)?);
-            if let Rparan = parser.current() {
-                break;
-            }
-            eat!(parser, Comma, "expected ')' or ',' in argument list")?;
+    loop {
+        if let Rparan = parser.current() {
+            break;
         }
-        parser.next();
-        ret = ASTNode::CallExpr(name, args);
+        args.push(parse_expr(parser)?);
+        if let Rparan = parser.current() {
+            break;
+        }
+        eat!(parser, Comma, "expected ')' or ',' in argument list")?;
     }
-    return Some(ret);
+    parser.next();
+    return Some(args);
 }
 
-// Index
-fn parse_index(parser: &mut Parser) -> Option<ASTNode> {
+// Calls and indexes, which can be chained (`handlers[0](x)`)
+fn parse_call(parser: &mut Parser) -> Option<ASTNode> {
     // Get expr
-    let mut ret = parse_call(parser)?;
-    // Parse index
-    while let Lbracket = parser.current() {
-        parser.next();
-        let index = parse_expr(parser);
-        ret = ASTNode::IndexExpr(Box::new(ret), Box::new(index?));
-        // Eat ']'
-        eat!(parser, Rbracket, "expected ']' at end of index")?;
+    let start = parser.stream();
+    let mut ret = parse_base_expr(parser)?;
+    loop {
+        match parser.current() {
+            // Call
+            Lparan => {
+                let args = parse_args(parser)?;
+                ret = ASTNode::CallExpr(
+                    Box::new(ret), args, parser.span(&start)
+                );
+            },
+            // Index
+            Lbracket => {
+                parser.next();
+                let index = parse_expr(parser)?;
+                // Eat ']'
+                eat!(parser, Rbracket, "expected ']' at end of index")?;
+                ret = ASTNode::IndexExpr(
+                    Box::new(ret), Box::new(index), parser.span(&start)
+                );
+            },
+            _ => break,
+        }
     }
     return Some(ret);
 }
 
 // Unary
 fn parse_unary(parser: &mut Parser) -> Option<ASTNode> {
-    if vec![Minus, Not].contains(&parser.current()) {
+    let start = parser.stream();
+    if [Minus, Not].contains(&parser.current()) {
         let op = parser.current();
         parser.next();
-        return Some(ASTNode::UnaryExpr(op, Box::new(parse_unary(parser)?)));
+        let val = parse_unary(parser)?;
+        return Some(ASTNode::UnaryExpr(
+            op, Box::new(val), parser.span(&start)
+        ));
     }
-    if vec![PlusPlus, MinusMinus].contains(&parser.current()) {
+    if [PlusPlus, MinusMinus].contains(&parser.current()) {
         let op = parser.current();
         if let Identifier(mut v) = parser.next() {
             parser.next();
             v = parser.name.clone() + "::" + &v;
-            return Some(ASTNode::UnaryExpr(op, Box::new(ASTNode::VarExpr(v))));
+            return Some(ASTNode::UnaryExpr(
+                op, Box::new(ASTNode::VarExpr(v, parser.span(&start))),
+                parser.span(&start)
+            ));
         } else {
             error!(parser, "++/-- require identifiers");
             return Option::None;
         }
     }
-    return parse_index(parser);
+    return parse_call(parser);
 }
 
 // Binops
@@ -198,6 +253,7 @@ fn parse_binop_helper(
     can_repeat: bool
 ) -> Option<ASTNode> {
     // Lower precedence op
+    let start = parser.stream();
     let mut expr = callback(parser)?;
     while tokens.contains(&parser.current()) {
         // Left arg
@@ -207,7 +263,9 @@ fn parse_binop_helper(
         // Right arg
         let right = callback(parser)?;
         // Make binop
-        expr = ASTNode::BinopExpr(Box::new(expr), op, Box::new(right));
+        expr = ASTNode::BinopExpr(
+            Box::new(expr), op, Box::new(right), parser.span(&start)
+        );
         if !can_repeat {
             return Some(expr);
         }
@@ -247,7 +305,7 @@ fn parse_binop_set(parser: &mut Parser) -> Option<ASTNode> {
         let ret = parse_binop_helper(parser, vec![
             Equals, PlusEquals, MinusEquals, TimesEquals, DivEquals, ModEquals
         ], &parse_binop_logic, false)?;
-        let ASTNode::BinopExpr(rhs, op, _) = ret.clone() else {
+        let ASTNode::BinopExpr(rhs, op, _, _) = ret.clone() else {
             return Some(ret);
         };
         // Check that the binop is a setter
@@ -268,56 +326,50 @@ fn parse_binop_set(parser: &mut Parser) -> Option<ASTNode> {
 }
 
 // Lists
-fn parse_list_item(parser: &mut Parser, at: i32) -> (String, Option<ASTNode>) {
-    // Parses a single item in a list
-    let mut name: String;
-    // Get the key name
-    if let Identifier(n) = parser.current() {
-        // Use identifier name
-        name = n;
+fn parse_list_item(parser: &mut Parser) -> Option<(Option<ASTNode>, ASTNode)> {
+    // Parses a single item in a list, the key is None if it doesn't have one
+    // Identifier keys are strings (`[name: 7]`)
+    if let Identifier(name) = parser.current() {
+        let start = parser.stream();
         parser.next();
         if let Colon = parser.current() {
             parser.next();
+            return Some((Some(ASTNode::StringExpr(name)), parse_expr(parser)?));
         } else if let Comma | Rbracket = parser.current() {
             // Named indexes don't need values
-            return (name.clone(), Some(ASTNode::VarExpr(
-                parser.name.clone() + "::" + &name
-            )));
-        } else {
-            // It's not a named index (`[myvar + 1]`)
-            name = at.to_string();
-            parser.at -= 1;
+            let var = ASTNode::VarExpr(
+                parser.name.clone() + "::" + &name, parser.span(&start)
+            );
+            return Some((Some(ASTNode::StringExpr(name)), var));
         }
-    } else {
-        // Use number index
-        name = at.to_string();
+        // It's not a named index (`[myvar + 1]`)
+        parser.at -= 1;
+    }
+    // Any other key is an expression (`[1 + 1: "two"]`)
+    let val = parse_expr(parser)?;
+    if let Colon = parser.current() {
+        parser.next();
+        return Some((Some(val), parse_expr(parser)?));
     }
-    // Parse value
-    let val = parse_expr(parser);
-    return (name, val);
+    return Some((Option::None, val));
 }
 fn parse_list(parser: &mut Parser) -> Option<ASTNode> {
     // Parses a list
     eat!(parser, Lbracket, "expecting [")?;
     // Parse elements
-    let mut at: i32 = 0;
-    let mut names: Vec<String> = vec![];
+    let mut keys: Vec<ASTNode> = vec![];
     let mut vals: Vec<ASTNode> = vec![];
     let mut fastlist = true;
     while parser.current() != Rbracket {
-        let (name, val) = parse_list_item(parser, at);
-        // Invalid element
-        if val.is_none() {
+        let Some((key, val)) = parse_list_item(parser) else {
             // Parse until the end of the list so there aren't trailing errors
             while parser.next() != Semicolon {}
             return Option::None;
-        }
-        // Valid element
-        names.push(name.clone());
-        if fastlist {
-            fastlist = name.as_bytes()[0].is_ascii_digit();
-        }
-        vals.push(val?);
+        };
+        // Valid element, items without keys get the next index
+        fastlist = fastlist && key.is_none();
+        keys.push(key.unwrap_or(ASTNode::NoneExpr));
+        vals.push(val);
         // Eat comma
         if parser.current() == Comma {
             if parser.next() == Rbracket {
@@ -326,26 +378,72 @@ fn parse_list(parser: &mut Parser) -> Option<ASTNode> {
         } else if parser.current() == Rbracket {
         } else {
             error!(parser, "expected comma or ']'");
-            if parser.current() == Colon {
-                error!(parser, "identifiers are used as keys", ErrType::Hint);
-            }
             while parser.next() != Semicolon {}
             return Option::None;
         }
-        // Increment index
-        at += 1;
     }
     eat!(parser, Rbracket, "expecting ]")?;
-    return Some(ASTNode::ListExpr(names, vals, fastlist));
+    return Some(ASTNode::ListExpr(keys, vals, fastlist));
+}
+
+// Variables, private functions, and module items (`util.init`)
+fn parse_var(parser: &mut Parser, name: String) -> Option<ASTNode> {
+    let start = parser.stream();
+    let mangled = parser.name.clone() + "::" + &name;
+    if let Dot = parser.tokens[parser.at + 1].token {} else {
+        parser.next();
+        // Private functions are named `file:name`
+        if parser.args.private.contains(&mangled) {
+            return Some(ASTNode::VarExpr(
+                parser.name.clone() + "::" + &parser.name + ":" + &name,
+                parser.span(&start)
+            ));
+        }
+        return Some(ASTNode::VarExpr(mangled, parser.span(&start)));
+    }
+    let Some(module) = parser.args.modules.get(&mangled).cloned() else {
+        error!(parser, format!("no import called {}", name).as_str());
+        error!(
+            parser, "try importing it with `import(\"...\") as name;`",
+            ErrType::Hint
+        );
+        return Option::None;
+    };
+    // Eat the alias and dot
+    parser.next();
+    parser.next();
+    let Identifier(item) = parser.current() else {
+        error!(parser, "expected a name after '.'");
+        return Option::None;
+    };
+    if module.functis.contains(&item) {
+        parser.next();
+        return Some(ASTNode::VarExpr(
+            parser.name.clone() + "::" + &module.prefix + &item,
+            parser.span(&start)
+        ));
+    } else if module.globals.contains(&item) {
+        parser.next();
+        return Some(ASTNode::VarExpr(
+            module.file + "::" + &item, parser.span(&start)
+        ));
+    } else if module.private.contains(&item) {
+        error!(parser, format!(
+            "{} is private to {}", item, module.file
+        ).as_str());
+    } else {
+        error!(parser, format!(
+            "{} has no function or global called {}", name, item
+        ).as_str());
+    }
+    return Option::None;
 }
 
 // Normal expressions
 fn parse_base_expr(parser: &mut Parser) -> Option<ASTNode> {
     return match parser.current() {
         // Inbuilt type
-        Identifier(v) => { parser.next(); Some(ASTNode::VarExpr(
-            parser.name.clone() + "::" + &v
-        ))     },
+        Identifier(v) => parse_var(parser, v),
         Str(s)        => { parser.next(); Some(ASTNode::StringExpr(s))  },
         Int(i)        => { parser.next(); Some(ASTNode::NumberExpr(i))  },
         Float(f)      => { parser.next(); Some(ASTNode::DecimalExpr(f)) },
@@ -354,6 +452,8 @@ fn parse_base_expr(parser: &mut Parser) -> Option<ASTNode> {
         Byte(b)       => { parser.next(); Some(ASTNode::ByteExpr(b))    },
         // Lists
         Lbracket => parse_list(parser),
+        // Anonymous functions
+        Func(_) => parse_functi_expr(parser),
         // Nested expressions
         Lparan => {
             parser.next();
@@ -410,6 +510,8 @@ fn parse_statement(parser: &mut Parser) -> Option<ASTNode> {
         },
         // Return
         Return => parse_return(parser),
+        // Break/continue
+        Break | Continue => parse_loop_jump(parser),
         // If
         If => parse_if(parser),
         Else => {
@@ -417,12 +519,24 @@ fn parse_statement(parser: &mut Parser) -> Option<ASTNode> {
             parser.next();
             Option::None
         },
+        // Try/catch
+        Try => parse_try(parser),
+        Catch => {
+            error!(parser, "missing previous try statement");
+            parser.next();
+            Option::None
+        },
         // Imports
         Import => {
             error!(parser, "imports must be at highest level");
             parser.next();
             Option::None
         },
+        Private => {
+            error!(parser, "private must be at highest level");
+            parser.next();
+            Option::None
+        },
         // Semicolons
         Semicolon => {
             if parser.next() == Eof {
@@ -457,6 +571,7 @@ fn parse_body(parser: &mut Parser) -> Option<ASTNode> {
             break;
         }
         // Add statement
+        let line = parser.tokens[parser.at].stream.line as u32;
         let Some(stmt) = parse_statement(parser) else {
             if Eof == parser.current() && !parser.has_err {
                 break;
@@ -465,6 +580,7 @@ fn parse_body(parser: &mut Parser) -> Option<ASTNode> {
             continue;
         };
         if stmt != ASTNode::Nop {
+            body.push(ASTNode::LineStmt(line));
             body.push(stmt);
         }
     }
@@ -507,7 +623,37 @@ fn parse_if(parser: &mut Parser) -> Option<ASTNode> {
     ));
 }
 
+// Try/catch
+fn parse_try(parser: &mut Parser) -> Option<ASTNode> {
+    // Eat try
+    parser.next();
+    let body = parse_body(parser)?;
+    // Catch
+    eat!(parser, Catch, "expected catch after try")?;
+    eat!(parser, Lparan, "expected '(' after catch")?;
+    let Identifier(var) = parser.current() else {
+        error!(parser, "expected variable name");
+        return Option::None;
+    };
+    let var = parser.name.clone() + "::" + &var;
+    parser.next();
+    eat!(parser, Rparan, "expected ')' after catch variable")?;
+    let catch_body = parse_body(parser)?;
+    return Some(ASTNode::TryStmt(
+        Box::new(body), var, Box::new(catch_body)
+    ));
+}
+
 // Loops
+fn parse_loop_body(parser: &mut Parser) -> Option<ASTNode> {
+    // Allow break/continue
+    let old_in_loop = parser.in_loop;
+    parser.in_loop = true;
+    let body = parse_body(parser);
+    parser.in_loop = old_in_loop;
+    return body;
+}
+
 fn parse_loop_iter(parser: &mut Parser) -> Option<ASTNode> {
     // Name
     let name: String;
@@ -523,8 +669,9 @@ fn parse_loop_iter(parser: &mut Parser) -> Option<ASTNode> {
     // Iterator
     let mut iter = parse_expr(parser)?;
     // Range optimization
-    if let ASTNode::CallExpr(name, args) = iter.clone() {
-        if name == *"range" {
+    let range = parser.name.clone() + "::range";
+    if let ASTNode::CallExpr(name, args, span) = iter.clone() {
+        if matches!(*name, ASTNode::VarExpr(ref functi, _) if *functi == range) {
             if args.len() != 2 {
                 // Arg check
                 error!(
@@ -534,13 +681,15 @@ fn parse_loop_iter(parser: &mut Parser) -> Option<ASTNode> {
                 return Option::None;
             }
             // Use the faster range
-            iter = ASTNode::CallExpr("__burlap_range".to_string(), args);
+            iter = ASTNode::CallExpr(Box::new(ASTNode::VarExpr(
+                parser.name.clone() + "::__burlap_range", span
+            )), args, span);
         }
     }
     // End parens
     eat!(parser, Rparan, "missing ')' in loop")?;
     // Body
-    let body = parse_body(parser)?;
+    let body = parse_loop_body(parser)?;
     // Return
     return Some(ASTNode::LoopStmt(name, Box::new(iter), Box::new(body)));
 }
@@ -553,7 +702,7 @@ fn parse_loop_while(parser: &mut Parser) -> Option<ASTNode> {
     // End parens
     eat!(parser, Rparan, "missing ')' in loop")?;
     // Body
-    let body = parse_body(parser)?;
+    let body = parse_loop_body(parser)?;
     // Return
     return Some(ASTNode::WhileStmt(Box::new(cond), Box::new(body)));
 }
@@ -585,42 +734,246 @@ fn parse_import(parser: &mut Parser) -> Option<(String, Vec<ASTNode>)> {
     parser.next();
     // The closing parens
     eat!(parser, Rparan, "missing ')' in import")?;
+    // Alias, `as util`
+    let mut alias: Option<String> = Option::None;
+    if let As = parser.current() {
+        let Identifier(a) = parser.next() else {
+            error!(parser, "expected a name after as");
+            return Option::None;
+        };
+        alias = Some(a);
+        parser.next();
+    }
 
     // Everything parsed well, now for the tricky part; importing
     let old_name = parser.args.name.clone();
     let old_path = parser.args.path.clone();
-    let path = &mut parser.args.path;
-    path.pop();
-    path.push(file.clone());
-    // Try x.sk
-    path.set_extension("sk");
-    if let Ok(src) = read_to_string(path.to_str().unwrap()) {
-        parser.args.source = src;
-        parser.args.name = path.clone().into_os_string().into_string().unwrap();
-    } else {
-        // Try x.sack
-        path.set_extension("sack");
-        if let Ok(src) = read_to_string(path.to_str().unwrap()) {
-            parser.args.source = src;
-            parser.args.name = path.clone().into_os_string().into_string().unwrap();
-        } else {
-            // No such file
-            error!(parser, format!("cannot import {}", file).as_str());
-            return Option::None;
+    let mut found = false;
+    // The standard library is built in
+    if let Some((name, src)) = get_std_module(&file) {
+        parser.args.source = src.to_string();
+        parser.args.path = PathBuf::from(&name);
+        parser.args.name = name;
+        found = true;
+    }
+    // Try x.sk then x.sack in each directory
+    let mut tried: Vec<PathBuf> = vec![];
+    let dirs = if found { vec![] } else { import_search_path(&parser.args) };
+    'search: for dir in dirs {
+        for ext in ["sk", "sack"] {
+            let mut path = dir.join(&file);
+            path.set_extension(ext);
+            if tried.contains(&path) {
+                continue;
+            }
+            if let Ok(src) = read_to_string(&path) {
+                parser.args.source = src;
+                parser.args.name = path.to_string_lossy().to_string();
+                parser.args.path = path;
+                found = true;
+                break 'search;
+            }
+            tried.push(path);
         }
     }
+    // No such file
+    if !found {
+        error!(parser, format!("cannot import {}", file).as_str());
+        let color = parser.args.extensions.contains(&"color".to_string());
+        for path in tried {
+            print_err(
+                format!("tried {}", path.display()).as_str(),
+                ErrType::Note, color
+            );
+        }
+        if file.starts_with("std/") {
+            print_err(
+                format!(
+                    "the std modules are {}", std_module_names().join(", ")
+                ).as_str(),
+                ErrType::Note, color
+            );
+        }
+        return Option::None;
+    }
 
-    // Return
-    let ret = to_ast(&mut parser.args)?;
     let name = parser.args.name.clone();
-    parser.args.name = old_name;
-    parser.args.path = old_path;
+    let key = import_key(&name);
+    // Imports that would import themselves
+    parser.args.import_chain.push((import_key(&old_name), old_name.clone()));
+    let chain = &parser.args.import_chain;
+    if let Some(at) = chain.iter().position(|i| i.0 == key) {
+        let mut files: Vec<&str> = chain[at..].iter()
+            .map(|i| i.1.as_str()).collect();
+        files.push(&name);
+        let msg = format!("cyclic import: {}", files.join(" -> "));
+        parser.args.name = old_name;
+        parser.args.path = old_path;
+        error!(parser, msg.as_str());
+        return Option::None;
+    }
+
+    // Files are only imported once
+    let mut ret = vec![];
+    if let Some(module) = parser.args.imported.get(&key).cloned() {
+        parser.args.name = old_name;
+        parser.args.path = old_path;
+        if alias.is_none() && !module.prefix.is_empty() {
+            error!(parser, format!(
+                "{} was already imported with an alias", name
+            ).as_str());
+            error!(parser, "use the alias instead", ErrType::Hint);
+            return Option::None;
+        }
+        if let Some(alias) = alias {
+            let key = parser.name.clone() + "::" + &alias;
+            parser.args.modules.insert(key, module);
+        }
+    } else {
+        let ast = to_ast(&mut parser.args);
+        parser.args.name = old_name;
+        parser.args.path = old_path;
+        ret = ast?;
+        let module = make_module(&mut ret, name.clone(), alias.is_some());
+        parser.args.imported.insert(key, module.clone());
+        if let Some(alias) = alias {
+            let key = parser.name.clone() + "::" + &alias;
+            parser.args.modules.insert(key, module);
+        }
+    }
+
+    parser.args.import_chain.pop();
 
     // Semicolon
     eat_semicolon!(parser)?;
     return Some((name, ret));
 }
 
+// Where imports are looked for, in order
+fn import_search_path(args: &Arguments) -> Vec<PathBuf> {
+    // Next to the importing file
+    let mut dir = args.path.clone();
+    dir.pop();
+    let mut ret = vec![dir];
+    // `-I dir`
+    ret.extend(args.import_dirs.iter().cloned());
+    // `BURLAP_PATH`
+    if let Some(paths) = env::var_os("BURLAP_PATH") {
+        ret.extend(env::split_paths(&paths));
+    }
+    // The user's library directory
+    if let Some(home) = home_dir() {
+        ret.push(home.join(".burlap_lib"));
+    }
+    return ret;
+}
+
+// The same file always has the same key, however it's imported
+fn import_key(name: &str) -> String {
+    return match canonicalize(name) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        // The REPL
+        Err(_) => name.to_string(),
+    };
+}
+
+// Finds what an import has, aliased imports are renamed to `file:name`
+fn make_module(ast: &mut [ASTNode], file: String, aliased: bool) -> Module {
+    let mut module = Module { file, ..Default::default() };
+    let prefix = module.file.clone() + "::";
+    let functi_prefix = module.file.clone() + ":";
+    for node in ast.iter() {
+        match node {
+            // Already renamed functions are private or from aliased imports
+            ASTNode::FunctiStmt(name, ..) => {
+                if let Some(name) = name.strip_prefix(&functi_prefix) {
+                    module.private.push(name.to_string());
+                } else if !name.contains(':') {
+                    module.functis.push(name.clone());
+                }
+            },
+            ASTNode::LetStmt(name, _) => {
+                if let Some(name) = name.strip_prefix(&prefix) {
+                    module.globals.push(name.to_string());
+                }
+            },
+            ASTNode::PrivateStmt(stmt) => {
+                let ASTNode::LetStmt(name, _) = &**stmt else {
+                    panic!("{}", IMPOSSIBLE_STATE);
+                };
+                if let Some(name) = name.strip_prefix(&prefix) {
+                    module.private.push(name.to_string());
+                }
+            },
+            _ => {},
+        }
+    }
+    // Rename the functions so they don't clash with the importer's
+    if aliased {
+        for node in ast.iter_mut() {
+            rename_functis(node, &module.functis, &module.file);
+        }
+        module.prefix = module.file.clone() + ":";
+    }
+    return module;
+}
+
+// Renames every use of the functions in `names` to `file:name`
+fn rename_functis(node: &mut ASTNode, names: &[String], file: &str) {
+    let rename = |name: &mut String| {
+        let (prefix, n) = name.rsplit_once("::").unwrap_or(("", name));
+        if names.iter().any(|i| i == n) {
+            *name = format!("{}::{}:{}", prefix, file, n);
+        }
+    };
+    let recurse = |node: &mut ASTNode| rename_functis(node, names, file);
+    match node {
+        ASTNode::VarExpr(name, _) => rename(name),
+        ASTNode::FunctiStmt(name, args, body, _) => {
+            if names.contains(name) {
+                *name = format!("{}:{}", file, name);
+            }
+            args.iter_mut().for_each(rename);
+            recurse(body);
+        },
+        ASTNode::FunctiExpr(args, body) => {
+            args.iter_mut().for_each(rename);
+            recurse(body);
+        },
+        ASTNode::LetStmt(name, val) => {
+            rename(name);
+            recurse(val);
+        },
+        ASTNode::LoopStmt(name, a, b) | ASTNode::TryStmt(a, name, b) => {
+            rename(name);
+            recurse(a);
+            recurse(b);
+        },
+        ASTNode::CallExpr(a, nodes, _) => {
+            recurse(a);
+            nodes.iter_mut().for_each(recurse);
+        },
+        ASTNode::ListExpr(keys, nodes, _) => {
+            keys.iter_mut().for_each(recurse);
+            nodes.iter_mut().for_each(recurse);
+        },
+        ASTNode::BodyStmt(nodes) => nodes.iter_mut().for_each(recurse),
+        ASTNode::IndexExpr(a, b, _) | ASTNode::BinopExpr(a, _, b, _)
+        | ASTNode::WhileStmt(a, b) => {
+            recurse(a);
+            recurse(b);
+        },
+        ASTNode::IfStmt(a, b, c) => {
+            recurse(a);
+            recurse(b);
+            recurse(c);
+        },
+        ASTNode::UnaryExpr(_, a, _) | ASTNode::ReturnStmt(a)
+        | ASTNode::PrivateStmt(a) => recurse(a),
+        _ => {},
+    }
+}
+
 // Variable definition
 fn parse_let(parser: &mut Parser) -> Option<ASTNode> {
     // Eat let
@@ -692,25 +1045,26 @@ fn parse_return(parser: &mut Parser) -> Option<ASTNode> {
     return Some(ASTNode::ReturnStmt(Box::new(ret_val)));
 }
 
-// Functions
-fn parse_functi(parser: &mut Parser) -> Option<ASTNode> {
-    // Disallow functions in functions
-    if parser.in_func {
+// Break/continue
+fn parse_loop_jump(parser: &mut Parser) -> Option<ASTNode> {
+    let (node, name) = match parser.current() {
+        Break => (ASTNode::BreakStmt, "break"),
+        _ => (ASTNode::ContinueStmt, "continue"),
+    };
+    // Loop check
+    if !parser.in_loop {
+        error!(parser, format!("{} outside of loops", name).as_str());
         parser.next();
-        error!(parser, "cannot create function in function");
-        return Option::None;
-    }
-    // Eat functi
-    parser.next();
-    // Name
-    let name: String;
-    if let Identifier(n) = parser.current() {
-        name = n;
-    } else {
-        error!(parser, "expected function name");
         return Option::None;
     }
     parser.next();
+    eat_semicolon!(parser)?;
+    return Some(node);
+}
+
+// Functions
+// Parses the arg names and body, `(a, b) { ... }`
+fn parse_functi_def(parser: &mut Parser) -> Option<(Vec<String>, ASTNode)> {
     // Args
     eat!(parser, Lparan, "expected '(' at start of argument list")?;
     let mut args: Vec<String> = vec![];
@@ -747,23 +1101,99 @@ fn parse_functi(parser: &mut Parser) -> Option<ASTNode> {
         );
         return Option::None;
     }
+    // Loops outside don't count
+    let (old_in_func, old_in_loop) = (parser.in_func, parser.in_loop);
     parser.in_func = true;
+    parser.in_loop = false;
     let body = parse_body(parser);
-    parser.in_func = false;
+    parser.in_func = old_in_func;
+    parser.in_loop = old_in_loop;
+    return Some((args, body?));
+}
+
+fn parse_functi(parser: &mut Parser) -> Option<ASTNode> {
+    // Anonymous functions are expressions
+    if let Lparan = parser.tokens[parser.at + 1].token {
+        let ret = parse_binop_set(parser)?;
+        eat_semicolon!(parser)?;
+        return Some(ret);
+    }
+    // Disallow functions in functions
+    if parser.in_func {
+        parser.next();
+        error!(parser, "cannot create function in function");
+        error!(
+            parser, "use an anonymous function (`let f = functi(...) { ... };`)",
+            ErrType::Hint
+        );
+        return Option::None;
+    }
+    // Eat functi
+    parser.next();
+    // Name
+    let start = parser.stream();
+    let name: String;
+    if let Identifier(n) = parser.current() {
+        // Private functions are named `file:name`
+        if parser.args.private.contains(&(parser.name.clone() + "::" + &n)) {
+            name = parser.name.clone() + ":" + &n;
+        } else {
+            name = n;
+        }
+    } else {
+        error!(parser, "expected function name");
+        return Option::None;
+    }
+    parser.next();
+    let span = parser.span(&start);
+    let (args, body) = parse_functi_def(parser)?;
     // Return
-    return Some(ASTNode::FunctiStmt(name, args, Box::new(body?)));
+    return Some(ASTNode::FunctiStmt(name, args, Box::new(body), span));
+}
+
+// Anonymous functions, `functi(a, b) { ... }`
+fn parse_functi_expr(parser: &mut Parser) -> Option<ASTNode> {
+    // Eat functi
+    parser.next();
+    let (args, body) = parse_functi_def(parser)?;
+    return Some(ASTNode::FunctiExpr(args, Box::new(body)));
+}
+
+// Private functions and globals, `private functi f() { ... }`
+fn parse_private(parser: &mut Parser) -> Option<ASTNode> {
+    // Eat private
+    parser.next();
+    return match parser.current() {
+        Func(_) if matches!(parser.tokens[parser.at + 1].token, Identifier(_))
+            => parse_functi(parser),
+        Let => Some(ASTNode::PrivateStmt(Box::new(parse_let(parser)?))),
+        _ => {
+            error!(parser, "expected a function or let after private");
+            Option::None
+        },
+    };
 }
 
 // Main parsing
-pub fn parse(tokens: Vec<Token>, args: &Arguments) -> Option<Vec<ASTNode>> {
+pub fn parse(tokens: Vec<Token>, args: &mut Arguments) -> Option<Vec<ASTNode>> {
     if tokens.is_empty() {
         return Some(vec![]);
     }
+    // Private functions can be used before they're defined
+    let private = tokens.windows(3).filter_map(|t| {
+        let (Private, Func(_), Identifier(n)) = (
+            &t[0].token, &t[1].token, &t[2].token
+        ) else {
+            return Option::None;
+        };
+        return Some(args.name.clone() + "::" + n);
+    }).collect::<FxHashSet<String>>();
+    args.private.extend(private);
     // Set up
     // TODO: Line numbers
     let mut parser = Parser{
         tokens, args: args.clone(),
-        at: 0, has_err: false, in_func: false,
+        at: 0, has_err: false, in_func: false, in_loop: false,
         ast: vec![], name: args.name.clone()
     };
     // Parse
@@ -771,7 +1201,7 @@ pub fn parse(tokens: Vec<Token>, args: &Arguments) -> Option<Vec<ASTNode>> {
         // Import must be highest scope
         if parser.current() == Import {
             if let Some((path, mut imported_ast)) = parse_import(&mut parser) {
-                parser.ast.push(ASTNode::ImportStmt());
+                parser.ast.push(ASTNode::ImportStmt(path.clone()));
                 parser.ast.append(&mut imported_ast);
                 parser.ast.push(ASTNode::EndImportStmt(path));
             } else {
@@ -779,7 +1209,14 @@ pub fn parse(tokens: Vec<Token>, args: &Arguments) -> Option<Vec<ASTNode>> {
             };
             continue;
         }
-        if let Some(stmt) = parse_statement(&mut parser) {
+        let line = parser.tokens[parser.at].stream.line as u32;
+        let stmt = if parser.current() == Private {
+            parse_private(&mut parser)
+        } else {
+            parse_statement(&mut parser)
+        };
+        if let Some(stmt) = stmt {
+            parser.ast.push(ASTNode::LineStmt(line));
             parser.ast.push(stmt);
             continue;
         };
@@ -791,6 +1228,10 @@ pub fn parse(tokens: Vec<Token>, args: &Arguments) -> Option<Vec<ASTNode>> {
             parser.next();
         }
     }
+    // Aliases and private functions are kept for later REPL lines
+    args.modules = std::mem::take(&mut parser.args.modules);
+    args.private = std::mem::take(&mut parser.args.private);
+    args.imported = std::mem::take(&mut parser.args.imported);
     // Return
     if parser.has_err {
         return Option::None;
@@ -2,7 +2,7 @@ use crate::vm::{run, Vm};
 #[cfg(feature = "fancyrepl")]
 use crate::lexer::{lex, TokenType};
 use crate::compiler::compile;
-use crate::common::{print_err, ErrType};
+use crate::common::{print_err, set_repl_line, ErrType};
 use crate::{Arguments, to_ast};
 
 #[cfg(feature = "fancyrepl")]
@@ -12,13 +12,15 @@ use rustyline::{Helper, Hinter, Validator};
 use rustyline::error::ReadlineError;
 #[cfg(feature = "fancyrepl")]
 use rustyline::Editor;
+#[cfg(feature = "fancyrepl")]
+use rustyline::history::DefaultHistory;
 #[cfg(not(feature = "fancyrepl"))]
 use rustyline::DefaultEditor;
 use home::home_dir;
 
 #[cfg(feature = "fancyrepl")]
 #[derive(Helper, Hinter, Validator)]
-struct FancyRepl {
+pub struct FancyRepl {
     #[rustyline(Validator)]
     brackets: MatchingBracketValidator,
     color: bool,
@@ -37,7 +39,7 @@ impl rustyline::highlight::Highlighter for FancyRepl {
         }
         // Lex
         let tokens = lex(
-            &line.to_string(), self.name.clone(), false, self.color
+            line, self.name.clone(), false, self.color
         ).unwrap_or(vec![]);
         if tokens.is_empty() {
             return std::borrow::Cow::Borrowed(line);
@@ -49,12 +51,15 @@ impl rustyline::highlight::Highlighter for FancyRepl {
                 // Misc keywords
                 TokenType::Let | TokenType::Func(..)
                     | TokenType::Import | TokenType::Bool(..)
-                    | TokenType::None => Some("\x1b[32m"),
+                    | TokenType::None | TokenType::As
+                    | TokenType::Private => Some("\x1b[32m"),
                 // Control flow
                 TokenType::If | TokenType::Else | TokenType::In
                     | TokenType::Str(..) | TokenType::While
-                    | TokenType::Loop => Some("\x1b[1;33m"),
-                TokenType::Return => Some("\x1b[35m"),
+                    | TokenType::Loop | TokenType::Try
+                    | TokenType::Catch => Some("\x1b[1;33m"),
+                TokenType::Return | TokenType::Break
+                    | TokenType::Continue => Some("\x1b[35m"),
                 // Comments
                 TokenType::Skipped => Some("\x1b[1;36m"),
                 // Operators
@@ -85,7 +90,8 @@ impl rustyline::highlight::Highlighter for FancyRepl {
                 | TokenType::Rparan | TokenType::Lbracket
                 | TokenType::Lbrace | TokenType::Rbrace
                 | TokenType::Rbracket | TokenType::Semicolon
-                | TokenType::Comma | TokenType::Identifier(_) => None,
+                | TokenType::Comma | TokenType::Dot
+                | TokenType::Identifier(_) => None,
             };
             // Add the color
             if let Some(c) = color {
@@ -107,7 +113,7 @@ impl rustyline::highlight::Highlighter for FancyRepl {
 // Completion
 #[cfg(feature = "fancyrepl")]
 // Wrapper around String because it implements traits)
-struct Candidate {
+pub struct Candidate {
     str: String
 }
 #[cfg(feature = "fancyrepl")]
@@ -128,7 +134,7 @@ impl rustyline::completion::Completer for FancyRepl {
         &self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>
     ) -> rustyline::Result<(usize, Vec<Candidate>)> {
         let tokens = lex(
-            &line.to_string(), self.name.clone(), false, self.color
+            line, self.name.clone(), false, self.color
         ).unwrap_or(vec![]);
         let mut target: Option<String> = None;
         let mut start = 0;
@@ -170,14 +176,73 @@ impl rustyline::completion::Completer for FancyRepl {
     }
 }
 
-pub fn get_repl_line() -> &'static mut String {
-    unsafe {
-        // Hack to make errors in repl mode work
-        static mut LINE: Option<String> = None;
-        if LINE == None {
-            LINE = Some("".to_string());
+// The line editor, shared by the REPL and the debugger
+pub struct LineEditor {
+    #[cfg(feature = "fancyrepl")]
+    rl: Editor<FancyRepl, DefaultHistory>,
+    #[cfg(not(feature = "fancyrepl"))]
+    rl: DefaultEditor,
+    hist_file: String,
+    color: bool,
+}
+
+impl LineEditor {
+    pub fn new(args: &Arguments, _symbols: Vec<String>) -> LineEditor {
+        let color = args.extensions.contains(&"color".to_string());
+        #[cfg(feature = "fancyrepl")]
+        let mut rl = Editor::new().unwrap();
+        #[cfg(not(feature = "fancyrepl"))]
+        let mut rl = DefaultEditor::new().unwrap();
+        #[cfg(feature = "fancyrepl")]
+        // Helpers
+        rl.set_helper(Some(FancyRepl{
+            brackets: MatchingBracketValidator::new(),
+            name: args.name.clone(), color,
+            symbols: _symbols
+        }));
+        // Try to get the home dir
+        let hist_file = match home_dir() {
+            Some(path) =>
+                path.into_os_string().into_string().unwrap()
+                    + "/.burlap_history",
+            None => "".to_string(),
+        };
+        // Load history
+        if !hist_file.is_empty() && rl.load_history(&hist_file).is_err() {
+            print_err("failed to open history file", ErrType::Warn, color);
+            print_err(
+                "create `~/.burlap_history` if you want history to save.",
+                ErrType::Hint, color
+            );
+        };
+        LineEditor { rl, hist_file, color }
+    }
+
+    // Get a line and add it to the history
+    pub fn readline(&mut self, prompt: &str) -> Result<String, ReadlineError> {
+        let line = self.rl.readline(prompt)?;
+        if !self.hist_file.is_empty() {
+           self.rl.add_history_entry(line.clone())
+                .expect("failed to add line to history");
+        }
+        return Ok(line);
+    }
+
+    // Update tab-completion
+    pub fn set_symbols(&mut self, _symbols: Vec<String>) {
+        #[cfg(feature = "fancyrepl")]
+        {
+            self.rl.helper_mut().unwrap().symbols = _symbols;
+        }
+    }
+
+    // Save history
+    pub fn save(&mut self) {
+        if !self.hist_file.is_empty()
+            && self.rl.save_history(&self.hist_file).is_err()
+        {
+            print_err("failed to save history", ErrType::Warn, self.color);
         }
-        return LINE.as_mut().unwrap();
     }
 }
 
@@ -185,33 +250,7 @@ pub fn repl(args: &mut Arguments) {
     // Print welcome msg
     println!("Burlap v{}", env!("CARGO_PKG_VERSION"));
     let mut vm = Vm::new(args.clone());
-    #[cfg(feature = "fancyrepl")]
-    let mut rl = Editor::new().unwrap();
-    #[cfg(not(feature = "fancyrepl"))]
-    let mut rl = DefaultEditor::new().unwrap();
-    #[cfg(feature = "fancyrepl")]
-    // Helpers
-    rl.set_helper(Some(FancyRepl{
-        brackets: MatchingBracketValidator::new(),
-        name: args.name.clone(),
-        color: args.extensions.contains(&"color".to_string()),
-        symbols: vm.get_symbols(true)
-    }));
-    // Try to get the home dir
-    let hist_file = match home_dir() {
-        Some(path) =>
-            path.into_os_string().into_string().unwrap() + "/.burlap_history",
-        None => "".to_string(),
-    };
-    // Load history
-    if !hist_file.is_empty() && rl.load_history(&hist_file).is_err() {
-        let color = args.extensions.contains(&"color".to_string());
-        print_err("failed to open history file", ErrType::Warn, color);
-        print_err(
-            "create `~/.burlap_history` if you want history to save.",
-            ErrType::Hint, color
-        );
-    };
+    let mut rl = LineEditor::new(args, vm.get_symbols(true));
     // REPL loop
     loop {
         // Get input
@@ -222,13 +261,8 @@ pub fn repl(args: &mut Arguments) {
         }
         // Input
         if let Ok(line) = readline {
-            // Add to history
-            if !hist_file.is_empty() {
-               rl.add_history_entry(line.clone())
-                    .expect("failed to add line to history");
-            }
             args.source = line + ";";
-            *get_repl_line() = args.source.clone();
+            set_repl_line(&args.source);
             // Gen ast
             let Some(ast) = to_ast(args) else {
                 continue;
@@ -249,17 +283,8 @@ pub fn repl(args: &mut Arguments) {
             }
             run(&mut vm);
             // Update symbols
-            #[cfg(feature = "fancyrepl")]
-            {
-                rl.helper_mut().unwrap().symbols = vm.get_symbols(true);
-            }
+            rl.set_symbols(vm.get_symbols(true));
         }
     }
-    // Save history
-    if !hist_file.is_empty() && rl.save_history(&hist_file).is_err() {
-        print_err(
-            "failed to save history", ErrType::Warn,
-            args.extensions.contains(&"color".to_string())
-        );
-    }
+    rl.save();
 }
@@ -0,0 +1,26 @@
+// The standard library, sack modules that are built into the binary
+// `import("std/strings");` imports std/strings.sk
+
+const MODULES: [(&str, &str); 3] = [
+    ("assert", include_str!("../std/assert.sk")),
+    ("lists", include_str!("../std/lists.sk")),
+    ("strings", include_str!("../std/strings.sk")),
+];
+
+// Gets the file name and source of an import (`std/strings`)
+pub fn get_std_module(import: &str) -> Option<(String, &'static str)> {
+    let name = import.strip_prefix("std/")?;
+    let (_, src) = MODULES.iter().find(|m| m.0 == name)?;
+    return Some((format!("<std>/{}.sk", name), src));
+}
+
+// Gets the source of a module from its file name (`<std>/strings.sk`)
+pub fn get_std_source(name: &str) -> Option<&'static str> {
+    let name = name.strip_prefix("<std>/")?.strip_suffix(".sk")?;
+    return MODULES.iter().find(|m| m.0 == name).map(|m| m.1);
+}
+
+// The names of all the modules, for errors
+pub fn std_module_names() -> Vec<String> {
+    return MODULES.iter().map(|m| format!("std/{}", m.0)).collect();
+}
@@ -1,7 +1,33 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::fmt;
 use std::fs::File;
+use std::hash::BuildHasherDefault;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
 use std::ops;
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
+use rustc_hash::FxHasher;
+
+use crate::common::IMPOSSIBLE_STATE;
+
+thread_local! {
+    // The f32-decimals extension, set by the VM that's running
+    static F32_DECIMALS: Cell<bool> = const { Cell::new(false) };
+}
+
+// Makes decimals round to 32 bits, like they used to
+pub fn set_f32_decimals(on: bool) {
+    F32_DECIMALS.with(|f| f.set(on));
+}
+
+fn f32_decimals() -> bool {
+    return F32_DECIMALS.with(|f| f.get());
+}
 
 #[derive(Debug)]
 pub struct FileInfo {
@@ -17,60 +43,416 @@ impl PartialEq for FileInfo {
     }
 }
 
-// Value enum for variables
-// TODO: Speed up (pointer tagging?)
+// A function value, builtins don't have an address
+#[derive(Debug, PartialEq)]
+pub struct FunctiInfo {
+    pub name: String,
+    pub pos: Option<usize>,
+    pub arg_num: i32,
+    // Captured variables, (name, value)
+    pub captures: Vec<(String, Value)>,
+}
+
+// A key in a keyed list, keys of different types are never the same key
+// (like `__burlap_typed_eq`), so `[1: "a"]["1"]` fails
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Str(Rc<String>),
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Bool(bool),
+    Byte(u8),
+}
+
+impl Key {
+    pub fn new(val: &Value) -> Result<Key, String> {
+        return Ok(match val.view() {
+            View::Str(_) => Key::Str(val.get_rc().expect(IMPOSSIBLE_STATE)),
+            View::Int(i) => Key::Int(i),
+            View::BigInt(_) => {
+                Key::BigInt(val.get_rc().expect(IMPOSSIBLE_STATE))
+            },
+            View::Bool(b) => Key::Bool(b),
+            View::Byte(b) => Key::Byte(b),
+            _ => return Err(
+                format!("{} can't be used as a key", val.get_type())
+            ),
+        });
+    }
+    pub fn to_value(&self) -> Value {
+        return match self {
+            Key::Str(s) => Value::Str(s.clone()),
+            Key::Int(i) => Value::Int(*i),
+            Key::BigInt(b) => Value::BigInt(b.clone()),
+            Key::Bool(b) => Value::Bool(*b),
+            Key::Byte(b) => Value::Byte(*b),
+        };
+    }
+}
+
+impl From<&str> for Key {
+    fn from(s: &str) -> Key {
+        return Key::Str(Rc::new(s.to_string()));
+    }
+}
+
+// A list with keys, hashed for lookups but kept in insertion order
+pub type KeyedList = IndexMap<Key, Value, BuildHasherDefault<FxHasher>>;
+
+// Finds where a key is in a keyed list, numbers that aren't keys are indexes
+pub fn find_key(list: &KeyedList, index: &Value) -> Option<usize> {
+    if let Ok(key) = Key::new(index) {
+        if let Some(at) = list.get_index_of(&key) {
+            return Some(at);
+        }
+    }
+    if let View::Int(_) | View::Float(_) = index.view() {
+        let at = index.to_int();
+        if at >= 0 && (at as usize) < list.len() {
+            return Some(at as usize);
+        }
+    }
+    return None;
+}
+
+// The key for an item without one, its index unless that's taken
+pub fn next_key(list: &KeyedList) -> Key {
+    let mut at = list.len() as i64;
+    while list.contains_key(&Key::Int(at)) {
+        at += 1;
+    }
+    return Key::Int(at);
+}
+
+// Values are NaN-boxed into 8 bytes, so the VM can copy them around cheaply
+// Decimals are stored as themselves (NaNs are all the same NaN), anything
+// else hides in the payload of a NaN:
+//   0x7ff8 | tag << 48 | payload for small values (ints, bools, ...)
+//   0xfff8 | tag << 48 | pointer for values behind an Rc (strings, lists, ...)
+const QNAN: u64 = 0x7ff8_0000_0000_0000;
+const HEAP: u64 = 0xfff8_0000_0000_0000;
+const PAYLOAD: u64 = (1 << 48) - 1;
+
+// Tags for small values, ints that don't fit in 48 bits are big ints
+const INT: u64 = 1;
+const BOOL: u64 = 2;
+const BYTE: u64 = 3;
+const NONE: u64 = 4;
+
+// Tags for values behind an Rc
+const STR: u64 = 0;
+const BIG: u64 = 1;
+const LIST: u64 = 2;
+const FAST_LIST: u64 = 3;
+const FILE: u64 = 4;
+const FUNCTI: u64 = 5;
+const ITER: u64 = 6;
+const PTR: u64 = 7;
+
+// Types that are stored behind an Rc, and their tag
+pub(crate) trait Heap: Sized {
+    const TAG: u64;
+}
+impl Heap for String { const TAG: u64 = STR; }
+impl Heap for BigInt { const TAG: u64 = BIG; }
+impl Heap for KeyedList { const TAG: u64 = LIST; }
+impl Heap for Vec<Value> { const TAG: u64 = FAST_LIST; }
+impl Heap for RefCell<FileInfo> { const TAG: u64 = FILE; }
+impl Heap for FunctiInfo { const TAG: u64 = FUNCTI; }
+impl Heap for IterState { const TAG: u64 = ITER; }
+// Pointers for ffi, they don't always fit in the payload
+impl Heap for usize { const TAG: u64 = PTR; }
+
+// Calls `$functi::<T>(ptr)` with the type of a heap value
+macro_rules! with_heap_type {
+    ($bits:expr, $functi:ident) => {{
+        let ptr = ($bits & PAYLOAD) as usize;
+        match ($bits >> 48) & 7 {
+            STR => $functi::<String>(ptr),
+            BIG => $functi::<BigInt>(ptr),
+            LIST => $functi::<KeyedList>(ptr),
+            FAST_LIST => $functi::<Vec<Value>>(ptr),
+            FILE => $functi::<RefCell<FileInfo>>(ptr),
+            FUNCTI => $functi::<FunctiInfo>(ptr),
+            ITER => $functi::<IterState>(ptr),
+            _ => $functi::<usize>(ptr),
+        }
+    }};
+}
+
+unsafe fn retain<T>(ptr: usize) {
+    Rc::increment_strong_count(ptr as *const T);
+}
+
+unsafe fn release<T>(ptr: usize) {
+    Rc::decrement_strong_count(ptr as *const T);
+}
+
+// The bits for a pointer to an Rc'd value
+fn heap_bits<T: Heap>(ptr: *const T) -> u64 {
+    let ptr = ptr as usize as u64;
+    assert!(ptr <= PAYLOAD, "pointers must fit in 48 bits");
+    return HEAP | T::TAG << 48 | ptr;
+}
+
+// The state of a loop, ranges are counted instead of made into a list
 #[derive(Debug, Clone, PartialEq)]
-pub enum Value {
+pub(crate) enum IterState {
+    List(Rc<Vec<Value>>, usize),
+    // (at, max, step), a step of 0 means it's done
+    Range(i64, i64, i64),
+}
+
+// A value, see the top of the file for how it's stored
+// Use `view` to see what's inside (`match val.view() { View::Int(i) => ...`)
+pub struct Value(u64, PhantomData<Rc<()>>);
+
+// What's in a value
+#[derive(Debug, PartialEq)]
+pub enum View<'a> {
     // Normal values
-    Str(String),
-    Int(i32),
-    Float(f32),
+    Str(&'a String),
+    // Every number that fits in an i64
+    Int(i64),
+    // Numbers that don't fit in an i64
+    BigInt(&'a BigInt),
+    Float(f64),
     Bool(bool),
     Byte(u8),
-    List(Vec<(String, Value)>),
+    List(&'a KeyedList),
     None,
-    File(Rc<RefCell<FileInfo>>),
+    File(&'a RefCell<FileInfo>),
+    Functi(&'a FunctiInfo),
 
     // FastList (used for lists with only number keys)
-    FastList(Vec<Value>),
+    FastList(&'a Vec<Value>),
 
     // Ptr, used for ffi
     #[cfg(feature = "cffi")]
     Ptr(usize),
 
     // Iterator (used for iter based loops)
-    Iter(Vec<Value>, i32),
+    Iter,
     // RangeType (used for optimized ranges)
-    RangeType(i32, i32, i32),
+    RangeType,
 }
 
-// Helper for ops
-macro_rules! do_op {
-    ($left:expr, $right:expr, $op:tt, $errval:expr) => {
-        match $left {
-            // Floats
-            Value::Float(f) => {
-                if let Value::Float(f_right) = $right {
-                    // Two floats are easy!
-                    Ok(Value::Float(*f $op *f_right))
-                } else if let Value::Int(i_right) = $right {
-                    // A float and an int are easier
-                    Ok(Value::Float(*f $op (*i_right as f32)))
-                } else {
-                    $errval
+// A value should stay one word big
+#[cfg(target_pointer_width = "64")]
+const _: () = assert!(std::mem::size_of::<Value>() == 8);
+
+impl Clone for Value {
+    #[inline]
+    fn clone(&self) -> Value {
+        if self.is_heap() {
+            unsafe { with_heap_type!(self.0, retain) };
+        }
+        return Value(self.0, PhantomData);
+    }
+}
+
+impl Drop for Value {
+    #[inline]
+    fn drop(&mut self) {
+        if self.is_heap() {
+            unsafe { with_heap_type!(self.0, release) };
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, right: &Value) -> bool {
+        // Loops aren't in the view
+        if let (Some(iter), Some(iter_right)) = (
+            self.get::<IterState>(), right.get::<IterState>()
+        ) {
+            return iter == iter_right;
+        }
+        return self.view() == right.view();
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return self.view().fmt(f);
+    }
+}
+
+// Constructors are named like the View variants they make
+#[allow(non_snake_case, non_upper_case_globals)]
+impl Value {
+    pub const None: Value = Value(QNAN | NONE << 48, PhantomData);
+    pub fn Int(i: i64) -> Value {
+        if (-(1 << 47)..1 << 47).contains(&i) {
+            return Value::small(INT, i as u64 & PAYLOAD);
+        }
+        return Value::heap(Rc::new(BigInt::from(i)));
+    }
+    pub fn Float(f: f64) -> Value {
+        if f.is_nan() {
+            return Value(QNAN, PhantomData);
+        }
+        return Value(f.to_bits(), PhantomData);
+    }
+    pub fn Bool(b: bool) -> Value {
+        return Value::small(BOOL, b as u64);
+    }
+    pub fn Byte(b: u8) -> Value {
+        return Value::small(BYTE, b as u64);
+    }
+    pub fn Str(s: Rc<String>) -> Value {
+        return Value::heap(s);
+    }
+    // Big ints that fit are Ints
+    pub fn BigInt(b: Rc<BigInt>) -> Value {
+        return match b.to_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::heap(b),
+        };
+    }
+    pub fn List(l: Rc<KeyedList>) -> Value {
+        return Value::heap(l);
+    }
+    pub fn FastList(l: Rc<Vec<Value>>) -> Value {
+        return Value::heap(l);
+    }
+    pub fn File(f: Rc<RefCell<FileInfo>>) -> Value {
+        return Value::heap(f);
+    }
+    pub fn Functi(f: Rc<FunctiInfo>) -> Value {
+        return Value::heap(f);
+    }
+    #[cfg(feature = "cffi")]
+    pub fn Ptr(ptr: usize) -> Value {
+        return Value::heap(Rc::new(ptr));
+    }
+}
+
+// The boxing
+impl Value {
+    fn small(tag: u64, payload: u64) -> Value {
+        return Value(QNAN | tag << 48 | payload, PhantomData);
+    }
+    fn heap<T: Heap>(val: Rc<T>) -> Value {
+        return Value(heap_bits(Rc::into_raw(val)), PhantomData);
+    }
+    pub(crate) fn iter(state: IterState) -> Value {
+        return Value::heap(Rc::new(state));
+    }
+
+    #[inline]
+    fn is_float(&self) -> bool {
+        return self.0 & QNAN != QNAN || self.0 == QNAN;
+    }
+    #[inline]
+    fn is_heap(&self) -> bool {
+        return self.0 & HEAP == HEAP;
+    }
+    #[inline]
+    fn tag(&self) -> u64 {
+        return (self.0 >> 48) & 7;
+    }
+    fn ptr<T: Heap>(&self) -> Option<*const T> {
+        if !self.is_heap() || self.tag() != T::TAG {
+            return None;
+        }
+        return Some((self.0 & PAYLOAD) as usize as *const T);
+    }
+
+    // Gets what's behind the Rc
+    pub(crate) fn get<T: Heap>(&self) -> Option<&T> {
+        return self.ptr().map(|ptr| unsafe { &*ptr });
+    }
+    // Gets what's behind the Rc to change, it's copied first if it's shared
+    pub(crate) fn get_mut<T: Heap + Clone>(&mut self) -> Option<&mut T> {
+        let ptr = self.ptr::<T>()?;
+        let mut rc = ManuallyDrop::new(unsafe { Rc::from_raw(ptr) });
+        let ret: *mut T = Rc::make_mut(&mut rc);
+        self.0 = heap_bits(Rc::as_ptr(&rc));
+        return Some(unsafe { &mut *ret });
+    }
+    // Gets another Rc to what's behind this one
+    pub(crate) fn get_rc<T: Heap>(&self) -> Option<Rc<T>> {
+        let ptr = self.ptr::<T>()?;
+        return Some(unsafe {
+            Rc::increment_strong_count(ptr);
+            Rc::from_raw(ptr)
+        });
+    }
+    // Takes the Rc out of the value
+    pub(crate) fn into_rc<T: Heap>(self) -> Option<Rc<T>> {
+        let ptr = self.ptr::<T>()?;
+        std::mem::forget(self);
+        return Some(unsafe { Rc::from_raw(ptr) });
+    }
+
+    // Unboxes the value
+    #[inline]
+    pub fn view(&self) -> View<'_> {
+        if self.is_float() {
+            return View::Float(f64::from_bits(self.0));
+        }
+        let payload = self.0 & PAYLOAD;
+        if !self.is_heap() {
+            return match self.tag() {
+                // Sign extend
+                INT => View::Int(((payload << 16) as i64) >> 16),
+                BOOL => View::Bool(payload != 0),
+                BYTE => View::Byte(payload as u8),
+                _ => View::None,
+            };
+        }
+        let ptr = payload as usize;
+        return unsafe { match self.tag() {
+            STR => View::Str(&*(ptr as *const String)),
+            BIG => {
+                let big = &*(ptr as *const BigInt);
+                match big.to_i64() {
+                    Some(i) => View::Int(i),
+                    None => View::BigInt(big),
                 }
             },
-            // Ints
-            Value::Int(i) => {
-                if let Value::Float(f_right) = $right {
-                    // Int and float -> float and float
-                    Ok(Value::Float((*i as f32) $op *f_right))
-                } else if let Value::Int(i_right) = $right {
-                    // Two ints
-                    Ok(Value::Int(*i $op *i_right))
-                } else {
-                    $errval
-                }
+            LIST => View::List(&*(ptr as *const KeyedList)),
+            FAST_LIST => View::FastList(&*(ptr as *const Vec<Value>)),
+            FILE => View::File(&*(ptr as *const RefCell<FileInfo>)),
+            FUNCTI => View::Functi(&*(ptr as *const FunctiInfo)),
+            ITER => match &*(ptr as *const IterState) {
+                IterState::List(..) => View::Iter,
+                IterState::Range(..) => View::RangeType,
+            },
+            #[cfg(feature = "cffi")]
+            PTR => View::Ptr(*(ptr as *const usize)),
+            _ => panic!("{}", IMPOSSIBLE_STATE),
+        } };
+    }
+}
+
+// Helper for ops, `$checked` is the int method that catches overflow
+macro_rules! do_op {
+    ($left:expr, $right:expr, $op:tt, $checked:ident, $errval:expr) => {
+        match ($left.view(), $right.view()) {
+            // Two ints, if it overflows it's done with big ints
+            (View::Int(i), View::Int(i_right)) => {
+                Ok(match i.$checked(i_right) {
+                    Some(i) => Value::Int(i),
+                    None => Value::from_big(
+                        BigInt::from(i) $op BigInt::from(i_right)
+                    ),
+                })
+            },
+            // Big ints
+            (
+                View::Int(_) | View::BigInt(_),
+                View::Int(_) | View::BigInt(_)
+            ) => {
+                Ok(Value::from_big($left.to_big() $op $right.to_big()))
+            },
+            // Anything with a float is done with floats
+            (
+                View::Int(_) | View::BigInt(_) | View::Float(_),
+                View::Int(_) | View::BigInt(_) | View::Float(_)
+            ) => {
+                Ok(Value::decimal($left.to_float() $op $right.to_float()))
             },
             // Anything else
             _ => {
@@ -82,54 +464,120 @@ macro_rules! do_op {
 
 // Methods
 impl Value {
+    // Constructors for the Rc'd types
+    pub fn str(s: impl Into<String>) -> Value {
+        return Value::Str(Rc::new(s.into()));
+    }
+    // Later keys replace earlier ones
+    pub fn list(l: Vec<(Key, Value)>) -> Value {
+        return Value::List(Rc::new(l.into_iter().collect()));
+    }
+    pub fn fast_list(l: Vec<Value>) -> Value {
+        return Value::FastList(Rc::new(l));
+    }
+    // Decimals, rounded to an f32 if the extension is on
+    pub fn decimal(f: f64) -> Value {
+        if f32_decimals() {
+            return Value::Float(f as f32 as f64);
+        }
+        return Value::Float(f);
+    }
+    // Big ints that fit are Ints
+    pub fn from_big(b: BigInt) -> Value {
+        return match b.to_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::BigInt(Rc::new(b)),
+        };
+    }
+    // Numbers as a big int
+    pub fn to_big(&self) -> BigInt {
+        return match self.view() {
+            View::BigInt(b) => b.clone(),
+            _ => BigInt::from(self.to_int()),
+        };
+    }
+    // To number conversion, for `int()`
+    pub fn to_number(&self) -> Value {
+        return match self.view() {
+            View::Str(s) => match s.parse::<i64>() {
+                Ok(i) => Value::Int(i),
+                Err(_) => s.parse().map(Value::from_big)
+                    .unwrap_or(Value::Int(0)),
+            },
+            View::BigInt(_) => self.clone(),
+            View::Float(f) => BigInt::from_f64(f).map(Value::from_big)
+                .unwrap_or(Value::Int(0)),
+            _ => Value::Int(self.to_int()),
+        };
+    }
     // To int conversion
-    pub fn to_int(&self) -> i32 {
-        return match self {
-            Value::Str(s) => s.parse::<i32>().unwrap_or(0),
-            Value::Int(i) => *i,
-            Value::Float(f) => *f as i32,
-            Value::Bool(b) => if *b { 1 } else { 0 },
-            Value::Byte(b) => *b as i32,
+    pub fn to_int(&self) -> i64 {
+        return match self.view() {
+            View::Str(s) => s.parse::<i64>().unwrap_or(0),
+            View::Int(i) => i,
+            // Too big, so it's as close as an i64 gets (see `to_int_arg`)
+            View::BigInt(b) => if b.sign() == num_bigint::Sign::Minus {
+                i64::MIN
+            } else {
+                i64::MAX
+            },
+            View::Float(f) => f as i64,
+            View::Bool(b) => b as i64,
+            View::Byte(b) => b as i64,
             #[cfg(feature = "cffi")]
-            Value::Ptr(ptr) => *ptr as i32,
+            View::Ptr(ptr) => ptr as i64,
             _ => 0,
         };
     }
+    // To int conversion for args that need an exact i64, `what` is for errors
+    pub fn to_int_arg(&self, what: &str) -> Result<i64, String> {
+        if let View::BigInt(_) = self.view() {
+            return Err(format!("{} must fit in 64 bits", what));
+        }
+        return Ok(self.to_int());
+    }
     // To float conversion
-    pub fn to_float(&self) -> f32 {
-        return match self {
-            Value::Str(s) => s.parse::<f32>().unwrap_or(0.0),
-            Value::Int(i) => *i as f32,
-            Value::Float(f) => *f,
-            Value::Bool(b) => if *b { 1.0 } else { 0.0 },
-            Value::Byte(b) => *b as f32,
+    pub fn to_float(&self) -> f64 {
+        return match self.view() {
+            View::Str(s) => s.parse::<f64>().unwrap_or(0.0),
+            View::Int(i) => i as f64,
+            View::BigInt(b) => b.to_f64().unwrap_or(f64::NAN),
+            View::Float(f) => f,
+            View::Bool(b) => b as u8 as f64,
+            View::Byte(b) => b as f64,
             _ => 0.0,
         };
     }
     // String conversion
     pub fn to_string(&self) -> Result<String, String> {
-        Ok(match self {
-            Value::Str(s) => s.clone(),
-            Value::Int(i) => format!("{}", i),
-            Value::Float(f) => format!("{:?}", f),
-            Value::Bool(b) => format!("{}", b),
-            Value::Byte(b) => {
+        Ok(match self.view() {
+            View::Str(s) => s.clone(),
+            View::Int(i) => format!("{}", i),
+            View::BigInt(b) => format!("{}", b),
+            // The shortest string that reads back as the same decimal
+            View::Float(f) => if f32_decimals() {
+                format!("{:?}", f as f32)
+            } else {
+                format!("{:?}", f)
+            },
+            View::Bool(b) => format!("{}", b),
+            View::Byte(b) => {
                 let mut ret = "0b".to_string();
                 for mask in (0..8).rev() {
-                    ret += if (*b & (1<<mask)) == 0 { "0" } else { "1" };
+                    ret += if (b & (1<<mask)) == 0 { "0" } else { "1" };
                 }
                 ret
             }
-            Value::List(l) => {
+            View::List(l) => {
                 let mut ret = "[".to_string();
                 // Add each element
-                for val in l.iter() {
-                    // The the index isn't a number, print the index
-                    if !val.0.as_bytes()[0].is_ascii_digit() {
-                        ret += &val.0;
+                for (at, (key, val)) in l.iter().enumerate() {
+                    // Keys that are just the index aren't printed
+                    if key != &Key::Int(at as i64) {
+                        ret += &key.to_value().to_string()?;
                         ret += ": ";
                     }
-                    ret += &val.1.to_string()?;
+                    ret += &val.to_string()?;
                     ret += ", ";
                 }
                 // Remove trailing ", "
@@ -139,10 +587,10 @@ impl Value {
                 ret += "]";
                 ret
             }
-            Value::FastList(l) => {
+            View::FastList(l) => {
                 let mut ret = "[".to_string();
                 // Add each element
-                for val in l {
+                for val in l.iter() {
                     ret += &(val.to_string()? + ", ");
                 }
                 // Remove trailing ", "
@@ -152,7 +600,8 @@ impl Value {
                 ret += "]";
                 ret
             }
-            Value::None => "none".to_string(),
+            View::None => "none".to_string(),
+            View::Functi(f) => format!("<functi {}>", f.name),
             // Anything else
             _ => return Err(
                 format!("Failed to convert {} to string", self.get_type())
@@ -161,111 +610,107 @@ impl Value {
     }
     // Truthy conversion
     pub fn is_truthy(&self) -> bool {
-        return match self {
-            Value::Str(s) => !s.is_empty(),
-            Value::Int(i) => *i != 0,
-            Value::Float(f) => *f != 0.0,
-            Value::Bool(b) => *b,
-            Value::List(l) => !l.is_empty(),
-            Value::FastList(l) => !l.is_empty(),
+        return match self.view() {
+            View::Str(s) => !s.is_empty(),
+            View::Int(i) => i != 0,
+            View::BigInt(_) => true,
+            View::Float(f) => f != 0.0,
+            View::Bool(b) => b,
+            View::Functi(_) => true,
+            View::List(l) => !l.is_empty(),
+            View::FastList(l) => !l.is_empty(),
             #[cfg(feature = "cffi")]
-            Value::Ptr(ptr) => *ptr != 0,
+            View::Ptr(ptr) => ptr != 0,
             _ => false,
         };
     }
     // Type
     pub fn get_type(&self) -> String {
-        return match self {
+        return match self.view() {
             // Normal types
-            Value::Str(_) => "String",
-            Value::Int(_) => "Number",
-            Value::Float(_) => "Decimal",
-            Value::Bool(_) => "Bool",
-            Value::Byte(_) => "Byte",
-            Value::List(_) | Value::FastList(_) => "List",
-            Value::None => "None",
-            Value::File(..) => "File",
+            View::Str(_) => "String",
+            View::Int(_) | View::BigInt(_) => "Number",
+            View::Float(_) => "Decimal",
+            View::Bool(_) => "Bool",
+            View::Byte(_) => "Byte",
+            View::List(_) | View::FastList(_) => "List",
+            View::None => "None",
+            View::File(..) => "File",
+            View::Functi(..) => "Function",
             // Internal types
             #[cfg(feature = "cffi")]
-            Value::Ptr(_) => "__burlap_ptr",
-            Value::Iter(..) => "__burlap_iter",
-            Value::RangeType(..) => "__burlap_rangetype",
+            View::Ptr(_) => "__burlap_ptr",
+            View::Iter => "__burlap_iter",
+            View::RangeType => "__burlap_rangetype",
         }.to_string();
     }
     // Lists
     pub fn values(&self) -> Option<Vec<Value>> {
-        if let Value::FastList(l) = self {
-            return Some(l.clone());
-        }
-        if let Value::List(l) = self {
-            return Some(l.iter().map(|i| i.1.clone()).collect());
-        }
-        None
+        return match self.view() {
+            View::FastList(l) => Some(l.clone()),
+            View::List(l) => Some(l.values().cloned().collect()),
+            _ => None,
+        };
     }
     // Iterators
     pub fn to_iter(&self) -> Result<Value, String> {
-        if let Value::RangeType(..) | Value::Iter(..) = self {
-            return Ok(self.clone());
-        }
-        if let Value::FastList(list) = self {
-            return Ok(Value::Iter(list.clone(), 0));
-        }
-        if let Value::Str(str) = self {
-            return Ok(Value::Iter(
-                str.lines().map(|s| Value::Str(s.to_string())).collect(), 0
-            ));
-        }
-        let Value::List(list) = self else {
-            return Err(format!("Cannot iterate over {}", self.get_type()));
+        let list = match self.view() {
+            View::RangeType | View::Iter => return Ok(self.clone()),
+            View::FastList(_) => self.get_rc().expect(IMPOSSIBLE_STATE),
+            View::Str(str) => Rc::new(str.lines().map(Value::str).collect()),
+            View::List(list) => Rc::new(list.values().cloned().collect()),
+            _ => return Err(
+                format!("Cannot iterate over {}", self.get_type())
+            ),
         };
-        return Ok(Value::Iter(
-            list.iter().map(|i| i.1.clone()).collect(), 0
-        ));
+        return Ok(Value::iter(IterState::List(list, 0)));
+    }
+    // An optimized range, counting from `at` to `max` by `step`
+    pub(crate) fn range(at: i64, max: i64, step: i64) -> Value {
+        return Value::iter(IterState::Range(at, max, step));
     }
     pub fn iter_next(&mut self) -> Result<Option<Value>, String> {
         // Must be an iter or rangetype
-        if let Value::RangeType(ref mut at, max, step) = self {
-            if *step == 0 {
-                // End of loop
-                return Ok(None);
-            }
-            if at == max {
-                // Final part
-                *step = 0;
-                return Ok(Some(Value::Int(*at)));
-            }
-            let ret = Value::Int(*at);
-            // Step
-            *at += *step;
-            return Ok(Some(ret));
-        }
-        // It's not rangetype, must be an iter
-        let Value::Iter(list, ref mut at) = self else {
+        let Some(iter) = self.get_mut::<IterState>() else {
             return Err(format!(
                 "Require __burlap_rangetype or __burlap_iter not {}",
                 self.get_type()
             ));
         };
-        // Get the value
-        let ret = list.get(*at as usize);
-        *at += 1;
-        // Return it
-        if let Some(ret) = ret {
-            return Ok(Some(ret.clone()));
-        } else {
-            return Ok(None);
+        match iter {
+            IterState::Range(at, max, step) => {
+                if *step == 0 {
+                    // End of loop
+                    return Ok(None);
+                }
+                if at == max {
+                    // Final part
+                    *step = 0;
+                    return Ok(Some(Value::Int(*at)));
+                }
+                let ret = Value::Int(*at);
+                // Step
+                *at += *step;
+                return Ok(Some(ret));
+            },
+            IterState::List(list, at) => {
+                // Get the value
+                let ret = list.get(*at).cloned();
+                *at += 1;
+                return Ok(ret);
+            },
         }
     }
     // Contains
     pub fn contains(&self, val: &Value) -> Option<bool> {
         if let Some(vals) = self.values() {
             return Some(vals.iter().any(|i| i.eq(val)));
-        } else if let Value::Str(str) = self {
-            if let Value::Str(vstr) = val {
-                return Some(str.contains(vstr));
-            } else if let Value::Byte(byte) = val {
+        } else if let View::Str(str) = self.view() {
+            if let View::Str(vstr) = val.view() {
+                return Some(str.contains(vstr.as_str()));
+            } else if let View::Byte(byte) = val.view() {
                 return Some(str.contains(
-                    &(*byte as char).to_string()
+                    &(byte as char).to_string()
                 ));
             }
         }
@@ -273,134 +718,107 @@ impl Value {
     }
     // Indexing
     pub fn index(&self, index: &Value) -> Option<Value> {
-        if let Value::Str(str) = self {
-            return if let Value::Int(i) = index {
-                if *i >= 0 {
-                    Some(Value::Str(str.chars().nth(*i as usize)?.to_string()))
+        if let View::Str(str) = self.view() {
+            return if let View::Int(i) = index.view() {
+                if i >= 0 {
+                    Some(Value::str(str.chars().nth(i as usize)?))
                 } else {
                     None
                 }
             } else {
                 None
             }
-        } else if let Value::FastList(list) = self {
+        } else if let View::FastList(list) = self.view() {
             // String indexing doesn't work
-            return if let Value::Str(_) = index {
+            return if let View::Str(_) = index.view() {
                 None
             } else {
                 list.get(index.to_int() as usize).cloned()
             }
         }
-        let Value::List(l) = self else {
+        let View::List(l) = self.view() else {
             // Not a list
             return None;
         };
-        // String indexing (keys)
-        if let Value::Str(s) = index {
-            for i in l {
-                if &i.0 == s {
-                    return Some(i.1.clone());
-                }
+        return l.get_index(find_key(l, index)?).map(|(_, v)| v.clone());
+    }
+    // Ordering for < and >, numbers are compared exactly if they're whole
+    pub fn compare(&self, right: &Value) -> Option<Ordering> {
+        return match (self.view(), right.view()) {
+            (View::Int(i), View::Int(i_right)) => Some(i.cmp(&i_right)),
+            (
+                View::Int(_) | View::BigInt(_),
+                View::Int(_) | View::BigInt(_)
+            ) => Some(self.to_big().cmp(&right.to_big())),
+            // Anything else is compared as decimals
+            _ => self.to_float().partial_cmp(&right.to_float()),
+        };
+    }
+    // Mutable indexing for lists, a shared list is copied first
+    pub fn index_mut(&mut self, index: &Value) -> Option<&mut Value> {
+        if let View::FastList(_) = self.view() {
+            // String indexing doesn't work
+            if let View::Str(_) = index.view() {
+                return None;
             }
-            return None;
+            let list = self.get_mut::<Vec<Value>>()?;
+            return list.get_mut(index.to_int() as usize);
         }
-        // Number indexing
-        return match l.get(index.to_int() as usize) {
-            // Extract the value and return
-            Some((_, v)) => Some(v.clone()),
-            None => None
-        };
+        let at = find_key(self.get()?, index)?;
+        let list = self.get_mut::<KeyedList>()?;
+        return list.get_index_mut(at).map(|(_, v)| v);
     }
-    // ==
+    // `+=`, lists are added to in place instead of being copied
+    pub fn add_assign(&mut self, right: Value) -> Result<(), String> {
+        if let Some(list) = self.get_mut::<Vec<Value>>() {
+            if let Some(mut vals) = right.values() {
+                list.append(&mut vals);
+            } else {
+                list.push(right);
+            }
+        } else if let Some(list) = self.get_mut::<KeyedList>() {
+            for val in right.values().unwrap_or_else(|| vec![right]) {
+                list.insert(next_key(list), val);
+            }
+        } else {
+            *self = (std::mem::replace(self, Value::None) + right)?;
+        }
+        return Ok(());
+    }
+    // ==, sack's equality (`1 == 1.0`) isn't the same as PartialEq's
+    #[allow(clippy::should_implement_trait)]
     pub fn eq(&self, right: &Value) -> bool {
-        return match self {
-            // str == ?
-            Value::Str(s) => {
-                if let Value::Str(s_right) = right {
-                    // str == str
-                    s == s_right
-                } else {
-                    false
-                }
+        return match (self.view(), right.view()) {
+            // Numbers of any kind
+            (View::Int(i), View::Int(i_right)) => i == i_right,
+            (View::BigInt(b), View::BigInt(b_right)) => b == b_right,
+            (View::Float(f), View::Float(f_right)) => f == f_right,
+            (View::Float(f), View::Int(_) | View::BigInt(_)) => {
+                f == right.to_float()
             },
-            // none == ?
-            Value::None => {
-                right == &Value::None
-            },
-            // bool == ?
-            Value::Bool(b) => {
-                // bool == bool
-                if let Value::Bool(b_right) = right {
-                    b == b_right
-                } else {
-                    false
-                }
-            },
-            // byte == ?
-            Value::Byte(b) => {
-                // byte == byte
-                if let Value::Byte(b_right) = right {
-                    b == b_right
-                } else {
-                    false
-                }
-            },
-            // Floats
-            Value::Float(f) => {
-                if let Value::Float(f_right) = right {
-                    // Two floats are easy!
-                    f == f_right
-                } else if let Value::Int(i_right) = right {
-                    // A float and an int also easy
-                    *f == *i_right as f32
-                } else {
-                    false
-                }
-            },
-            // Ints
-            Value::Int(i) => {
-                if let Value::Float(f_right) = right {
-                    // Int and float -> float and float
-                    *i as f32 == *f_right
-                } else if let Value::Int(i_right) = right {
-                    // Two ints
-                    i == i_right
-                } else {
-                    false
-                }
-            },
-            // Pointers
-            #[cfg(feature = "cffi")]
-            Value::Ptr(p) => {
-                if let Value::Ptr(p_right) = right {
-                    *p == p_right
-                } else {
-                    false
-                }
+            (View::Int(_) | View::BigInt(_), View::Float(f_right)) => {
+                self.to_float() == f_right
             },
             // Lists
-            Value::List(_) | Value::FastList(_) => {
+            (
+                View::List(_) | View::FastList(_),
+                View::List(_) | View::FastList(_)
+            ) => {
                 // This isn't spec defined, so may change in the future
-                let Some(rhs) = right.values() else {
-                    return false;
-                };
-                let Some(lhs) = self.values() else {
+                let (Some(lhs), Some(rhs)) = (self.values(), right.values())
+                else {
                     return false;
                 };
                 if lhs.len() != rhs.len() {
                     return false;
                 }
                 // Compare values
-                for ab in lhs.iter().zip(rhs.iter()) {
-                    let (a, b) = ab;
-                    if !a.eq(&b) {
-                        return false;
-                    }
-                }
-                true
+                lhs.iter().zip(rhs.iter()).all(|(a, b)| a.eq(b))
             },
-            // Anything else
-            _ => false,
+            // Files and loops are never equal
+            (View::File(_) | View::Iter | View::RangeType, _) => false,
+            // Anything else has to be the same type and value
+            (view, view_right) => view == view_right,
         };
     }
 }
@@ -408,24 +826,20 @@ impl Value {
 // Add
 impl_op_ex!(+ |left: &Value, right: &Value| -> Result<Value, String> {
     // Lists
-    if let Value::List(_) = left {
-        let Value::List(mut list) = left.clone() else {
-            panic!("impossible");
-        };
+    if let View::List(list) = left.view() {
+        let mut list = list.clone();
         if let Some(vals) = right.values() {
             // Concat
-            for val in vals.clone() {
-                list.push((list.len().to_string(), val));
+            for val in vals {
+                list.insert(next_key(&list), val);
             }
         } else {
             // Append
-            list.push((list.len().to_string(), right.clone()));
+            list.insert(next_key(&list), right.clone());
         }
-        return Ok(Value::List(list));
-    } else if let Value::FastList(_) = left {
-        let Value::FastList(mut list) = left.clone() else {
-            panic!("impossible");
-        };
+        return Ok(Value::List(Rc::new(list)));
+    } else if let View::FastList(list) = left.view() {
+        let mut list = list.clone();
         if let Some(mut vals) = right.values() {
             // Concat
             list.append(&mut vals);
@@ -433,37 +847,37 @@ impl_op_ex!(+ |left: &Value, right: &Value| -> Result<Value, String> {
             // Append
             list.push(right.clone());
         }
-        return Ok(Value::FastList(list));
+        return Ok(Value::fast_list(list));
     };
     // Strings
-    if let Value::Str(s) = right {
-        return Ok(Value::Str(left.to_string()? + &s));
-    } else if let Value::Str(s) = left {
-        return Ok(Value::Str(s.to_owned() + &right.to_string()?));
+    if let View::Str(s) = right.view() {
+        return Ok(Value::str(left.to_string()? + s.as_str()));
+    } else if let View::Str(s) = left.view() {
+        return Ok(Value::str(s.clone() + &right.to_string()?));
     }
     // Anything else
-    return do_op!(left, right, +, Err(
+    return do_op!(left, right, +, checked_add, Err(
         format!("Cannot add {} and {}", left.get_type(), right.get_type())
     ))
 });
 
 // Subtract
 impl_op_ex!(- |left: &Value, right: &Value| -> Result<Value, String> {
-    do_op!(left, right, -,
+    do_op!(left, right, -, checked_sub,
         Err(format!("Cannot subtract {} and {}", left.get_type(), right.get_type()))
     )
 });
 
 // Multiply
 impl_op_ex!(* |left: &Value, right: &Value| -> Result<Value, String> {
-    return match left {
+    return match left.view() {
         // str * number is valid
-        Value::Str(s) => {
-            if let Value::Int(i_right) = right {
-                Ok(if *i_right > 0 {
-                    Value::Str((*s).repeat((*i_right).try_into().unwrap()))
+        View::Str(s) => {
+            if let View::Int(i_right) = right.view() {
+                Ok(if i_right > 0 {
+                    Value::str(s.repeat(i_right.try_into().unwrap()))
                 } else {
-                    Value::Str("".to_string())
+                    Value::str("")
                 })
             } else {
                 Err(format!(
@@ -471,7 +885,7 @@ impl_op_ex!(* |left: &Value, right: &Value| -> Result<Value, String> {
                 ))
             }
         },
-        _ => do_op!(left, right, *,
+        _ => do_op!(left, right, *, checked_mul,
             Err(format!(
                 "Cannot multiply {} and {}", left.get_type(), right.get_type()
             ))
@@ -481,11 +895,11 @@ impl_op_ex!(* |left: &Value, right: &Value| -> Result<Value, String> {
 
 // Div
 impl_op_ex!(/ |left: &Value, right: &Value| -> Result<Value, String> {
-    if let Value::Int(i) = left {
-        return &Value::Float(*i as f32) / right;
+    if let View::Int(_) | View::BigInt(_) = left.view() {
+        return &Value::decimal(left.to_float()) / right;
     }
     do_op!(
-        left, right, /,
+        left, right, /, checked_div,
         Err(format!(
             "Cannot modulo {} and {}",
             left.get_type(),
@@ -496,8 +910,13 @@ impl_op_ex!(/ |left: &Value, right: &Value| -> Result<Value, String> {
 
 // Modulo
 impl_op_ex!(% |left: &Value, right: &Value| -> Result<Value, String> {
+    if let (View::Int(_) | View::BigInt(_), View::Int(0)) =
+        (left.view(), right.view())
+    {
+        return Err("cannot modulo by zero".to_string());
+    }
     do_op!(
-        left, right, %,
+        left, right, %, checked_rem,
         Err(format!(
             "Cannot modulo {} and {}",
             left.get_type(),
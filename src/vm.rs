@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::rc::Rc;
 use std::fs::OpenOptions;
 use std::io::{Write, Read, Seek, SeekFrom};
@@ -9,8 +10,12 @@ use crate::Arguments;
 use crate::cffi::{load_functi, load_library};
 #[cfg(feature = "cffi")]
 use crate::cffi::call as ffi_call;
+use crate::common::{err, line_stream, ErrType, Stream, IMPOSSIBLE_STATE};
 use crate::compiler::Program;
-use crate::value::{FileInfo, Value};
+use crate::value::{
+    next_key, set_f32_decimals, FileInfo, FunctiInfo, Key, KeyedList, Value,
+    View
+};
 
 use rustc_hash::FxHashMap;
 
@@ -38,10 +43,14 @@ pub enum Opcode {
     LEVI,
 
     // Functions
-    // declare FuNction (name, arg#)
-    FN,
+    // Load FuNction (captured names..., capture#, arg# -> functi)
+    LFN,
     // CALL function (name, arg#, args...)
     CALL,
+    // CALL Value (functi, arg#, args...)
+    CALLV,
+    // CALL Direct, the compiler checked the args ([u24 address], arg#, args...)
+    CALLD,
     // Tail Call ([u24], args...)
     TCO,
     // RETurn (ret)
@@ -56,6 +65,13 @@ pub enum Opcode {
     SV,
     // Declare Or Set variable ("name", value)
     DOS,
+    // Locals are resolved to slots by the compiler
+    // Push Local Variable ([u8 slot] -> value)
+    PLV,
+    // Set Local Variable ([u8 slot], value)
+    SLV,
+    // Declare Local Variable ("name", value), it goes in the next slot
+    DLV,
 
     // Lists
     // Load List (size, keys-and-values -> list)
@@ -70,6 +86,13 @@ pub enum Opcode {
     NXT,
     // Set KeY (value, list, key -> list)
     SKY,
+    // Set Key in Variable (value, keys..., key#, "name")
+    // Sets `name[keys[0]][keys[1]]... = value` without copying the list
+    SKV,
+    // Set Key in Local Variable ([u8 slot], value, keys..., key#)
+    SKLV,
+    // Add to Local Variable ([u8 slot], value), lists are added to in place
+    ALV,
 
     // Math
     // ADD (value, value -> value)
@@ -110,22 +133,54 @@ pub enum Opcode {
     JMPB,
     // JuMP if NoT ([u24], offset)
     JMPNT,
+
+    // Exceptions
+    // TRY, catch errors by jumping ([u24])
+    TRY,
+    // END TRY, stop catching errors
+    ENDTRY,
 }
 
 
 // A functie is a sack functions implemented in rust
-type Functie = fn(&mut Vm, Vec<Value>) -> Result<Value, String>;
+// They can be closures, so embedders can use their own state
+pub type Functie = Rc<dyn Fn(&mut Vm, Vec<Value>) -> Result<Value, String>>;
+type BuiltinFunctie = fn(&mut Vm, Vec<Value>) -> Result<Value, String>;
+
+// A function call, for args() and tracebacks
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    // Where the function starts, see `Program::functi_name`
+    pub pos: usize,
+    pub args: Vec<Value>,
+    // The CALL op that made the frame
+    pub call_site: usize,
+    // How many tail calls have reused the frame
+    pub tail_calls: usize,
+}
+
+// A snapshot of the VM, used to go back to a known state
+pub struct VmState {
+    at: usize,
+    stack: usize,
+    is_global: bool,
+    vars: usize,
+    var_min: usize,
+    scope: Vec<(usize, usize, u8)>,
+    call_frames: usize,
+    handlers: usize,
+}
+
+// A try block, where to go and the state to go back to
+pub struct Handler {
+    catch: usize,
+    state: VmState,
+}
 
 // VM state
 pub struct Vm {
     // Extensions
-    pub args: Arguments,
-    // File name
-    pub filename: String,
-
-    // State
-    pub has_err: bool,
-    pub in_func: bool,
+    pub(crate) args: Arguments,
 
     // Variables
     // Global vars
@@ -139,7 +194,9 @@ pub struct Vm {
     var_min: usize,
     // Scope
     scope: Vec<(usize, usize, u8)>,
-    call_frames: Vec<Vec<Value>>,
+    call_frames: Vec<CallFrame>,
+    // Try blocks, innermost last
+    pub handlers: Vec<Handler>,
 
     // The program
     pub program: Program,
@@ -151,77 +208,78 @@ pub struct Vm {
 
 impl Vm {
     // Init
-    pub fn new(args: Arguments) -> Vm {
+    pub(crate) fn new(args: Arguments) -> Vm {
         // Builtin functions
-        let mut functies = FxHashMap::with_capacity_and_hasher(
-            16, Default::default()
-        );
+        let mut functies: FxHashMap<String, Functie> =
+            FxHashMap::with_capacity_and_hasher(16, Default::default());
+        let mut add = |name: &str, functie: BuiltinFunctie| {
+            functies.insert(name.to_string(), Rc::new(functie));
+        };
         // Builtins
-        functies.insert("print".to_string(), sk_print as Functie);
-        functies.insert("input".to_string(), sk_input as Functie);
-        functies.insert("type".to_string(), sk_type as Functie);
-        functies.insert("len".to_string(), sk_len as Functie);
-        functies.insert("range".to_string(), sk_range as Functie);
-        functies.insert("args".to_string(), sk_args as Functie);
+        add("print", sk_print);
+        add("input", sk_input);
+        add("type", sk_type);
+        add("len", sk_len);
+        add("range", sk_range);
+        add("args", sk_args);
         // File IO
-        functies.insert("open".to_string(), sk_open as Functie);
-        functies.insert("close".to_string(), sk_close as Functie);
-        functies.insert("read".to_string(), sk_read as Functie);
-        functies.insert("write".to_string(), sk_write as Functie);
-        functies.insert("seek".to_string(), sk_seek as Functie);
-        functies.insert("flush".to_string(), sk_flush as Functie);
+        add("open", sk_open);
+        add("close", sk_close);
+        add("read", sk_read);
+        add("write", sk_write);
+        add("seek", sk_seek);
+        add("flush", sk_flush);
         // Casts
-        functies.insert("int".to_string(), sk_int as Functie);
-        functies.insert("float".to_string(), sk_float as Functie);
-        functies.insert("string".to_string(), sk_string as Functie);
-        functies.insert("byte".to_string(), sk_byte as Functie);
+        add("int", sk_int);
+        add("float", sk_float);
+        add("string", sk_string);
+        add("byte", sk_byte);
         // Non-togglable internals
-        functies.insert("__burlap_range".to_string(), sk_fastrange as Functie);
+        add("__burlap_range", sk_fastrange);
+        // The std library uses it
+        add("__burlap_throw", sk_throw);
         // Burlap internal functies
         if args.extensions.contains(&"burlap-extensions".to_string()) {
-            functies.insert(
-                "__burlap_typed_eq".to_string(), sk_typed_eq as Functie
-            );
-            functies.insert(
-                "__burlap_print".to_string(), sk_real_print as Functie
-            );
-            functies.insert(
-                "__burlap_throw".to_string(), sk_throw as Functie
-            );
+            add("__burlap_typed_eq", sk_typed_eq);
+            add("__burlap_print", sk_real_print);
             #[cfg(feature = "cffi")]
-            functies.insert(
-                "__burlap_load_lib".to_string(), sk_libload as Functie
-            );
+            add("__burlap_load_lib", sk_libload);
             #[cfg(feature = "cffi")]
-            functies.insert(
-                "__burlap_load_functi".to_string(), sk_functiload as Functie
-            );
+            add("__burlap_load_functi", sk_functiload);
             #[cfg(feature = "cffi")]
-            functies.insert(
-                "__burlap_ffi_call".to_string(), sk_call_c as Functie
-            );
+            add("__burlap_ffi_call", sk_call_c);
             #[cfg(feature = "cffi")]
-            functies.insert(
-                "__burlap_ptr".to_string(), sk_ptr as Functie
-            );
+            add("__burlap_ptr", sk_ptr);
         }
-        Vm {
-            args, has_err: false, in_func: false, functies,
+        // The compiler checks calls against them
+        let mut program = Program::new();
+        for name in functies.keys() {
+            program.add_native(name);
+        }
+        let vm = Vm {
+            args, functies,
             is_global: true, globals: FxHashMap::default(),
             var_names: vec![], var_vals: vec![], jump: false,
             stack: vec![], scope: vec![], call_frames: vec![],
-            at: 0, var_min: 0, program: Program::new(),
-            filename: "".to_string()
-        }
+            handlers: vec![], at: 0, var_min: 0, program,
+        };
+        vm.use_decimals();
+        return vm;
+    }
+
+    // The decimal extension is per thread, so VMs set it before running
+    pub fn use_decimals(&self) {
+        set_f32_decimals(
+            self.args.extensions.contains(&"f32-decimals".to_string())
+        );
     }
 
     // Unmangle a var name
-    fn unmangle(name: &String) -> String {
-        name.clone().split("::").nth(1).unwrap().to_string()
+    pub fn unmangle(name: &str) -> String {
+        name.split("::").nth(1).unwrap().to_string()
     }
 
     // Get a vec of all symbol names
-    #[cfg(feature = "fancyrepl")]
     pub fn get_symbols(&self, add_keywords: bool) -> Vec<String> {
         // Globals
         let mut ret: Vec<String>
@@ -240,14 +298,86 @@ impl Vm {
             .collect::<Vec<String>>());
         // Keywords
         if add_keywords {
-            ret.extend(vec![
-                "true", "false", "none", "functi", "let", "return", "in",
-                "if", "else", "loop", "while", "import",
+            ret.extend([
+                "true", "false", "none", "functi", "let", "return", "break",
+                "continue", "in",
+                "if", "else", "loop", "while", "import", "try", "catch",
+                "as", "private",
             ].iter().map(|i| i.to_string()).collect::<Vec<String>>());
         }
         return ret;
     }
 
+    // Debugging info
+    pub fn get_locals(&self) -> Vec<(String, Value)> {
+        if self.is_global {
+            return vec![];
+        }
+        return self.var_names[self.var_min..].iter().cloned()
+            .zip(self.var_vals[self.var_min..].iter().cloned())
+            .collect();
+    }
+
+    pub fn get_globals(&self) -> Vec<(String, Value)> {
+        let mut ret: Vec<(String, Value)> = self.globals.iter()
+            .map(|(k, v)| (k.clone(), v.clone())).collect();
+        ret.sort_by(|a, b| a.0.cmp(&b.0));
+        return ret;
+    }
+
+    pub fn get_call_frames(&self) -> &Vec<CallFrame> {
+        &self.call_frames
+    }
+
+    pub fn stack_len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn peek(&self) -> Option<&Value> {
+        self.stack.last()
+    }
+
+    // Saving and restoring
+    pub fn save_state(&self) -> VmState {
+        VmState {
+            at: self.at, stack: self.stack.len(), is_global: self.is_global,
+            vars: self.var_names.len(), var_min: self.var_min,
+            scope: self.scope.clone(), call_frames: self.call_frames.len(),
+            handlers: self.handlers.len(),
+        }
+    }
+
+    pub fn restore_state(&mut self, state: &VmState) {
+        self.at = state.at;
+        self.jump = false;
+        self.stack.truncate(state.stack);
+        self.is_global = state.is_global;
+        self.var_names.truncate(state.vars);
+        self.var_vals.truncate(state.vars);
+        self.var_min = state.var_min;
+        self.scope = state.scope.clone();
+        self.call_frames.truncate(state.call_frames);
+        self.handlers.truncate(state.handlers);
+    }
+
+    // Goes to the innermost catch, false if there isn't one
+    pub fn catch(&mut self, msg: &str) -> bool {
+        let Some(handler) = self.handlers.pop() else {
+            return false;
+        };
+        let (line, file) = self.program.get_info(self.at as u32);
+        // Unwind
+        self.restore_state(&handler.state);
+        self.push(Value::list(vec![
+            (Key::from("message"), Value::str(msg)),
+            (Key::from("file"), Value::str(file)),
+            (Key::from("line"), Value::Int(line as i64)),
+        ]));
+        self.at = handler.catch;
+        self.jump = true;
+        return true;
+    }
+
     // Getting vars
     fn get_local(&self, name: &String) -> Result<Value, String> {
         // Gets a local var
@@ -259,14 +389,14 @@ impl Vm {
             }
         }
         // Failed to get var, return an error
-        return Err(format!("no variable called \"{}\"", name));
+        return Err(no_var_err(name));
     }
 
-    fn get_global(&self, name: &String) -> Result<Value, String> {
+    pub fn get_global(&self, name: &String) -> Result<Value, String> {
         // Gets a var in the global scope
         return match self.globals.get(name) {
             Some(val) => Ok(val.clone()),
-            _ => Err(format!("no variable called \"{}\"", name))
+            _ => Err(no_var_err(name))
         };
     }
 
@@ -278,6 +408,19 @@ impl Vm {
         return self.get_local(name).or_else(|_| self.get_global(name));
     }
 
+    // Gets a variable to change in place
+    pub fn get_var_mut(
+        &mut self, name: &String
+    ) -> Result<&mut Value, String> {
+        if !self.is_global {
+            if let Some(index) = self.get_local_index(name) {
+                return Ok(&mut self.var_vals[index]);
+            }
+        }
+        return self.globals.get_mut(name)
+            .ok_or_else(|| no_var_err(name));
+    }
+
     pub fn check_for_var(&self, name: &String) -> bool {
         if !self.is_global {
             // Check locals
@@ -342,7 +485,7 @@ impl Vm {
         if self.set_global(name, val) {
             return Ok(());
         }
-        return Err(format!("no variable called \"{}\"", name));
+        return Err(no_var_err(name));
     }
 
     // Scope
@@ -379,43 +522,22 @@ impl Vm {
         Ok(())
     }
 
-    fn bad_args(
+    #[allow(clippy::ptr_arg)]
+    pub fn bad_args(
         &self, name: &String, got: usize, need: usize
     ) -> Result<(), String> {
-        Err(if got > need {
-            format!("too many args for {} (got {} need {})", name, got, need)
-        } else {
-            format!("too few args for {} (got {} need {})", name, got, need)
-        })
+        Err(args_err(name, got, need))
     }
 
-    // Call a function
+    // Jump into a sack function, the args have been checked
     #[inline]
-    pub fn call(
-        &mut self, name: &String, args: &Vec<Value>
-    ) -> Result<(), String> {
-
-        // Non-builtin functions
-        let Some((pos, arg_num)) = self.program.functis.get(name) else {
-            // Builtin functions
-            if let Some(functie) = self.functies.get(name) {
-                // Reverse (normally the vm pops the args in reverse)
-                let args = args.clone().into_iter().rev().collect();
-                let ret = functie(self, args)?;
-                self.push(ret);
-                return Ok(());
-            }
-            return Err(format!("no function called \"{}\"", name));
-        };
-        self.call_frames.push(args.clone().into_iter().rev().collect());
-        // Dereference
-        let (pos, arg_num) = (*pos, *arg_num);
-        // Check args
-        if arg_num != args.len() as i32 {
-            self.bad_args(name, args.len(), arg_num as usize)?;
-        }
+    fn enter(&mut self, pos: usize, args: &Vec<Value>) {
+        self.call_frames.push(CallFrame {
+            pos, args: args.clone().into_iter().rev().collect(),
+            call_site: self.at, tail_calls: 0,
+        });
         // Store return address
-        self.push(Value::Int(self.at as i32));
+        self.push(Value::Int(self.at as i64));
         // Jump there
         self.at = pos;
         self.jump = true;
@@ -425,6 +547,93 @@ impl Vm {
         }
         // Lower scope
         self.lower_scope(true);
+    }
+
+    // Call a builtin
+    fn call_functie(
+        &mut self, functie: Functie, args: &[Value]
+    ) -> Result<(), String> {
+        // Reverse (normally the vm pops the args in reverse)
+        let args = args.iter().rev().cloned().collect();
+        let ret = functie(self, args)?;
+        self.push(ret);
+        return Ok(());
+    }
+
+    // Adds (or replaces) a builtin function
+    pub fn add_functie(&mut self, name: &str, functie: Functie) {
+        self.program.add_native(name);
+        self.functies.insert(name.to_string(), functie);
+    }
+
+    // Gets a function as a value
+    pub fn get_functi(&self, name: &str) -> Option<Value> {
+        let info = if let Some((pos, arg_num)) = self.program.functis.get(name)
+        {
+            FunctiInfo{
+                name: name.to_string(), pos: Some(*pos), arg_num: *arg_num,
+                captures: vec![],
+            }
+        } else if self.functies.contains_key(name) {
+            // Builtins check their own args
+            FunctiInfo{
+                name: name.to_string(), pos: None, arg_num: -1,
+                captures: vec![],
+            }
+        } else {
+            return None;
+        };
+        return Some(Value::Functi(Rc::new(info)));
+    }
+
+    // Call a function by name
+    // Functions are checked first, then variables holding functions
+    #[inline]
+    pub fn call(
+        &mut self, name: &String, args: &Vec<Value>
+    ) -> Result<(), String> {
+        // Non-builtin functions
+        if let Some((pos, arg_num)) = self.program.functis.get(name) {
+            let (pos, arg_num) = (*pos, *arg_num);
+            if arg_num != args.len() as i32 {
+                self.bad_args(name, args.len(), arg_num as usize)?;
+            }
+            self.enter(pos, args);
+            return Ok(());
+        }
+        // Builtin functions
+        if let Some(functie) = self.functies.get(name).cloned() {
+            return self.call_functie(functie, args);
+        }
+        // Variables, which are mangled with the file they're in
+        let (_, file) = self.program.get_info(self.at as u32);
+        let Ok(val) = self.get_var(&format!("{}::{}", file, name)) else {
+            return Err(format!("no function called \"{}\"", name));
+        };
+        return self.call_value(&val, args);
+    }
+
+    // Call a function value
+    pub fn call_value(
+        &mut self, functi: &Value, args: &Vec<Value>
+    ) -> Result<(), String> {
+        let View::Functi(info) = functi.view() else {
+            return Err(format!("cannot call {}", functi.get_type()));
+        };
+        let Some(pos) = info.pos else {
+            let Some(functie) = self.functies.get(&info.name).cloned() else {
+                return Err(format!("no function called \"{}\"", info.name));
+            };
+            return self.call_functie(functie, args);
+        };
+        if info.arg_num != args.len() as i32 {
+            self.bad_args(&info.name, args.len(), info.arg_num as usize)?;
+        }
+        self.enter(pos, args);
+        // Captured variables are locals for the call
+        for (name, val) in &info.captures {
+            self.make_var(name, val.clone())?;
+        }
         return Ok(());
     }
 
@@ -434,7 +643,9 @@ impl Vm {
 
     pub fn cur_opcode(&mut self) -> Opcode {
         // Unsafe because casting an int to enum might not be valid
-        return unsafe {std::mem::transmute(self.program.ops[self.at])};
+        return unsafe {
+            std::mem::transmute::<u8, Opcode>(self.program.ops[self.at])
+        };
     }
 
     pub fn next_op(&mut self) -> u8 {
@@ -483,6 +694,21 @@ impl Vm {
     }
 }
 
+// The error for calling a function with the wrong number of args
+pub fn args_err(name: &str, got: usize, need: usize) -> String {
+    if got > need {
+        format!("too many args for {} (got {} need {})", name, got, need)
+    } else {
+        format!("too few args for {} (got {} need {})", name, got, need)
+    }
+}
+
+// The error for a missing variable, without the file it's mangled with
+fn no_var_err(name: &str) -> String {
+    let short = name.rsplit_once("::").map_or(name, |(_, short)| short);
+    return format!("no variable called \"{}\"", short);
+}
+
 // Builtin Functions (prefixed with 'sk_')
 // Print
 fn sk_print(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
@@ -514,8 +740,8 @@ fn sk_input(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
     // Get input
     let mut buffer = String::new();
     return Ok(match io::stdin().read_line(&mut buffer) {
-        Err(_) => Value::Str("".to_string()),
-        _ => Value::Str(buffer.trim_end().to_string())
+        Err(_) => Value::str(""),
+        _ => Value::str(buffer.trim_end())
     });
 }
 
@@ -525,7 +751,7 @@ fn sk_type(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         // Invalid args
         vm.bad_args(&"type".to_string(), args.len(), 1)?;
     }
-    return Ok(Value::Str(args[0].get_type()));
+    return Ok(Value::str(args[0].get_type()));
 }
 
 // Len
@@ -535,15 +761,12 @@ fn sk_len(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         vm.bad_args(&"len".to_string(), args.len(), 1)?;
     }
     // Get the len
-    let real_len: i32 = if let Value::FastList(l) = &args[0] {
-        l.len()
-    } else if let Value::List(l) = &args[0] {
-        l.len()
-    } else if let Value::Str(s) = &args[0] {
-        s.chars().count()
-    } else {
-        return Err("len() argument 1 must be a list".to_string());
-    } as i32;
+    let real_len = match args[0].view() {
+        View::FastList(l) => l.len(),
+        View::List(l) => l.len(),
+        View::Str(s) => s.chars().count(),
+        _ => return Err("len() argument 1 must be a list".to_string()),
+    } as i64;
     // Return
     if real_len == 0 {
         return Ok(Value::None);
@@ -558,9 +781,10 @@ fn sk_range(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         vm.bad_args(&"range".to_string(), args.len(), 2)?;
     }
     // Get the values
-    let (mut min, max) = (args[0].to_int(), args[1].to_int());
+    let mut min = args[0].to_int_arg("range() args")?;
+    let max = args[1].to_int_arg("range() args")?;
     // Find out which way it's going
-    let offset: i32 = if min > max { -1 } else { 1 };
+    let offset: i64 = if min > max { -1 } else { 1 };
     // Loop and get values
     let mut ret = Vec::<Value>::new();
     while min != max {
@@ -568,22 +792,22 @@ fn sk_range(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         min += offset;
     }
     ret.push(Value::Int(min));
-    return Ok(Value::FastList(ret));
+    return Ok(Value::fast_list(ret));
 }
 
 // Args
 fn sk_args(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
-    if args.len() != 0 {
+    if !args.is_empty() {
         // Invalid args
         vm.bad_args(&"args".to_string(), args.len(), 0)?;
     }
     // In a function
-    if vm.call_frames.len() != 0 {
-        return Ok(Value::FastList(vm.call_frames.last().unwrap().clone()));
+    if !vm.call_frames.is_empty() {
+        return Ok(Value::fast_list(vm.call_frames.last().unwrap().args.clone()));
     }
     // Global
-    return Ok(Value::FastList(
-        vm.args.program_args.iter().map(|x| Value::Str(x.clone())).collect()
+    return Ok(Value::fast_list(
+        vm.args.program_args.iter().map(|x| Value::str(x.as_str())).collect()
     ));
 }
 
@@ -593,11 +817,11 @@ fn sk_open(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         // Invalid args
         vm.bad_args(&"open".to_string(), args.len(), 2)?;
     }
-    let Value::Str(ref file) = args[0] else {
+    let View::Str(file) = args[0].view() else {
         return Err("invalid file name".to_string());
     };
     let file = file.to_string();
-    let Value::Str(ref mode) = args[1] else {
+    let View::Str(mode) = args[1].view() else {
         return Err("invalid file mode".to_string());
     };
     // Open with mode
@@ -606,12 +830,12 @@ fn sk_open(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         "w" | "wb" => {(
             OpenOptions::new().write(true).create(true).truncate(false)
                 .open(file.clone()),
-            if mode == "w" {2} else {-2}
+            if mode.as_str() == "w" {2} else {-2}
         )},
         // Read
         "r" | "rb" => {(
             OpenOptions::new().read(true).open(file.clone()),
-            if mode == "r" {1} else {-1}
+            if mode.as_str() == "r" {1} else {-1}
         )},
         // Append
         "a" => {(
@@ -633,12 +857,12 @@ fn sk_open(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
     return Ok(Value::File(fi));
 }
 
-fn sk_close(vm: &mut Vm, mut args: Vec<Value>) -> Result<Value, String> {
+fn sk_close(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
     if args.len() != 1 {
         // Invalid args
         vm.bad_args(&"close".to_string(), args.len(), 1)?;
     }
-    let Value::File(ref mut info) = args[0] else {
+    let View::File(info) = args[0].view() else {
         return Err(format!("cannot close {}", args[0].get_type()));
     };
     info.borrow_mut().closed = true;
@@ -655,12 +879,12 @@ fn sk_flush(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
     Ok(Value::None)
 }
 
-fn sk_read(vm: &mut Vm, mut args: Vec<Value>) -> Result<Value, String> {
+fn sk_read(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
     if args.len() != 1 {
         // Invalid args
         vm.bad_args(&"read".to_string(), args.len(), 1)?;
     }
-    let Value::File(ref mut info) = args[0] else {
+    let View::File(info) = args[0].view() else {
         return Err(format!("cannot read from {}", args[0].get_type()));
     };
     if info.borrow().closed {
@@ -681,9 +905,9 @@ fn sk_read(vm: &mut Vm, mut args: Vec<Value>) -> Result<Value, String> {
         let Ok(string) = String::from_utf8(ret) else {
             return Err("invalid string".to_string());
         };
-        return Ok(Value::Str(string));
+        return Ok(Value::str(string));
     }
-    return Ok(Value::FastList(ret.iter().map(|i| Value::Byte(*i)).collect()));
+    return Ok(Value::fast_list(ret.iter().map(|i| Value::Byte(*i)).collect()));
 }
 
 fn sk_seek(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
@@ -692,7 +916,7 @@ fn sk_seek(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         vm.bad_args(&"seek".to_string(), args.len(), 2)?;
     }
     // Check and get file
-    let Value::File(ref mut info) = args[0].clone() else {
+    let View::File(info) = args[0].view() else {
         return Err(format!("cannot seek on {}", args[0].get_type()));
     };
     if info.borrow().closed {
@@ -702,7 +926,7 @@ fn sk_seek(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         return Err("can only seek on 'w'/'wb'".to_string());
     }
     // Get position
-    let pos = args[1].to_int();
+    let pos = args[1].to_int_arg("position")?;
     if pos < 0 {
         return Err("position cannot be negative".to_string());
     }
@@ -724,7 +948,7 @@ fn sk_write(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         vm.bad_args(&"write".to_string(), args.len(), 2)?;
     }
     // Now check args
-    let Value::File(ref mut info) = args[0].clone() else {
+    let View::File(info) = args[0].view() else {
         return Err(format!("cannot write to {}", args[0].get_type()));
     };
     if info.borrow().closed {
@@ -734,7 +958,7 @@ fn sk_write(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
     if mode.abs() != 2 && mode != 0 {
         return Err("can only write to 'w'/'wb'/'a'".to_string());
     }
-    let Value::Str(ref str) = args[1] else {
+    let View::Str(str) = args[1].view() else {
         return Err(format!("expected String got {}", args[1].get_type()));
     };
     if let Err(err) =
@@ -752,7 +976,7 @@ fn sk_int(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         // Invalid args
         vm.bad_args(&"int".to_string(), args.len(), 1)?;
     }
-    return Ok(Value::Int(args[0].to_int()));
+    return Ok(args[0].to_number());
 }
 
 // Float
@@ -761,7 +985,7 @@ fn sk_float(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         // Invalid args
         vm.bad_args(&"float".to_string(), args.len(), 1)?;
     }
-    return Ok(Value::Float(args[0].to_float()));
+    return Ok(Value::decimal(args[0].to_float()));
 }
 
 // String
@@ -771,7 +995,7 @@ fn sk_string(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         vm.bad_args(&"string".to_string(), args.len(), 1)?;
     }
     // Bytes are a special case
-    return Ok(Value::Str(if let Value::Byte(byte) = args[0] {
+    return Ok(Value::str(if let View::Byte(byte) = args[0].view() {
         (byte as char).to_string()
     } else {
         let Ok(str) = args[0].to_string() else {
@@ -789,24 +1013,26 @@ fn sk_byte(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         // Invalid args
         vm.bad_args(&"byte".to_string(), args.len(), 1)?;
     }
-    Ok(match &args[0] {
+    Ok(match args[0].view() {
         // Strings
-        Value::Str(s) if s.chars().count() == 1 =>
-            Value::Byte(s.chars().nth(0).unwrap() as u8),
-        Value::Str(s) if s.chars().count() > 1 => {
+        View::Str(s) if s.chars().count() == 1 =>
+            Value::Byte(s.chars().next().unwrap() as u8),
+        View::Str(s) if s.chars().count() > 1 => {
             let mut ret: Vec<Value> = vec![];
             for chr in s.chars() {
                 ret.push(Value::Byte(chr as u8));
             }
-            Value::FastList(ret)
+            Value::fast_list(ret)
         }
-        Value::Str(_) =>
+        View::Str(_) =>
             return Err("cannot convert empty string to bytes".to_string()),
         // Int and identity
-        Value::Int(i) => Value::Byte((*i % 256).try_into().unwrap()),
-        Value::Byte(b) => Value::Byte(*b),
+        View::Int(i) => Value::Byte((i % 256).try_into().unwrap()),
+        View::Byte(b) => Value::Byte(b),
         // Anything else
-        e => return Err(format!("cannot convert {} to byte", e.get_type())),
+        _ => return Err(
+            format!("cannot convert {} to byte", args[0].get_type())
+        ),
     })
 }
 
@@ -817,10 +1043,10 @@ fn sk_ptr(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         // Invalid args
         vm.bad_args(&"__burlap_ptr".to_string(), args.len(), 1)?;
     }
-    if let Value::Ptr(_) = args[0].clone() {
+    if let View::Ptr(_) = args[0].view() {
         return Ok(args[0].clone());
     }
-    let num = args[0].to_int();
+    let num = args[0].to_int_arg("pointers")?;
     if num < 0 {
         return Err("Pointers can't be negative".to_string());
     }
@@ -866,7 +1092,7 @@ fn sk_functiload(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         vm.bad_args(&"__burlap_load_functi".to_string(), args.len(), 2)?;
     }
     // First arg must be library handle
-    let Value::Ptr(handle) = args[0] else {
+    let View::Ptr(handle) = args[0].view() else {
         return Err("First argument must be library handle".to_string());
     };
     return Ok(Value::Ptr(load_functi(handle, args[1].to_string()?)?));
@@ -878,7 +1104,7 @@ fn sk_call_c(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         vm.bad_args(&"__burlap_ffi_call".to_string(), args.len(), 3)?;
     }
     // First arg must be function pointer
-    let Value::Ptr(func) = args[0] else {
+    let View::Ptr(func) = args[0].view() else {
         return Err("First argument must be function pointer".to_string());
     };
     // Second arg must be list
@@ -886,94 +1112,94 @@ fn sk_call_c(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
         return Err("Second argument must be list".to_string());
     };
     // Third arg must be return value
-    let Value::Str(ret_type) = args[2].clone() else {
+    let View::Str(ret_type) = args[2].view() else {
         return Err(
             "Third argument must be string (try using `type()`?)".to_string()
         );
     };
-    return ffi_call(func, c_args, ret_type);
+    return ffi_call(func, c_args, ret_type.clone());
 }
 
 fn sk_fastrange(vm: &mut Vm, args: Vec<Value>) -> Result<Value, String> {
     if args.len() != 2 {
         vm.bad_args(&"__burlap_range".to_string(), args.len(), 2)?;
     }
-    let (at, max) = (args[0].to_int(), args[1].to_int());
+    let at = args[0].to_int_arg("range() args")?;
+    let max = args[1].to_int_arg("range() args")?;
     // For (0, 100) step is 1, for (100, 0) it's -1, etc..
     let step = if max.gt(&at) {1} else {-1};
-    return Ok(Value::RangeType(at, max, step));
+    return Ok(Value::range(at, max, step));
 }
 
-// Sets a key in a list and returns it
+// Sets a key in a list, the list is only copied if it's shared
 fn set_key(
-    vlist: Value, key: Value, val: Value
-) -> Result<Value, String> {
-    let Value::List(mut list) = vlist else {
-        let Value::FastList(mut list) = vlist else {
+    vlist: &mut Value, key: &Value, val: Value
+) -> Result<(), String> {
+    if key.compare(&Value::Int(0)) == Some(Ordering::Less) {
+        return Err("cannot assign to out of bounds key".to_string());
+    }
+    let View::List(_) = vlist.view() else {
+        let View::FastList(list) = vlist.view() else {
             return Err(format!(
                 "failed to index {} with {}",
                 vlist.to_string()?, key.to_string()?
             ));
         };
-        // Push
-        let as_int = key.to_int();
-        if key.get_type() == "Number" && as_int >= 0 {
-            let as_uint = as_int as usize;
-            let entry = list.get_mut(as_uint)
-                .map(|s| *s = val.clone());
-            if entry.is_none() {
-                if as_uint != list.len() + 1 {
-                    list.push(val);
-                } else {
-                    return Err(
-                        "cannot assign to out of bounds key".to_string()
-                    );
-                }
+        // Set or push, numbers past the end are out of bounds
+        if let View::Int(_) | View::BigInt(_) = key.view() {
+            let at = key.to_int() as usize;
+            if at > list.len() {
+                return Err("cannot assign to out of bounds key".to_string());
             }
-        } else {
-            // Convert to normal list
-            let mut slowlist = Vec::<(String, Value)>::with_capacity(
-                list.len()
-            );
-            let mut at = 0;
-            for i in list {
-                slowlist.push((at.to_string(), i));
-                at += 1;
+            let list = vlist.get_mut::<Vec<Value>>()
+                .expect(IMPOSSIBLE_STATE);
+            if let Some(entry) = list.get_mut(at) {
+                *entry = val;
+            } else {
+                list.push(val);
             }
-            // Set
-            return set_key(Value::List(slowlist), key, val);
+            return Ok(());
         }
-        return Ok(Value::FastList(list));
+        // Anything else makes it a keyed list
+        let keyed = list.iter().enumerate()
+            .map(|(at, i)| (Key::Int(at as i64), i.clone())).collect();
+        let mut keyed = Value::List(Rc::new(keyed));
+        set_key(&mut keyed, key, val)?;
+        *vlist = keyed;
+        return Ok(());
     };
-    // Insert
-    if key.get_type() == "Number" {
-        let key = key.to_int();
-        if key < 0 || key as usize > list.len() {
-            return Err(
-                "cannot assign to out of bounds key".to_string()
-            );
-        }
-        let key = key as usize;
-        if key == list.len() {
-            // Add new key
-            list.push((key.to_string(), val));
-        } else {
-            // Modify key
-            list[key].1 = val;
-        }
-    } else {
-        // Add or create string key
-        let key = key.to_string()?;
-        for mut i in &mut list {
-            if i.0 == key {
-                i.1 = val;
-                return Ok(Value::List(list));
-            }
+    // Modify, or add a new key, numbers are only keys here (not indexes)
+    let list = vlist.get_mut::<KeyedList>().expect(IMPOSSIBLE_STATE);
+    list.insert(Key::new(key)?, val);
+    return Ok(());
+}
+
+// Pops the keys and value for SKV and SKLV
+fn pop_keys(vm: &mut Vm) -> Result<(Vec<Value>, Value), String> {
+    let View::Int(key_num) = vm.pop().view() else {
+        return Err("Non-int key count".to_string());
+    };
+    let keys = vm.stack.split_off(vm.stack.len() - key_num as usize);
+    return Ok((keys, vm.pop()));
+}
+
+// Sets `var[keys[0]][keys[1]]... = val` in place
+fn set_keys(
+    mut var: &mut Value, keys: &[Value], val: Value
+) -> Result<(), String> {
+    let Some((last, keys)) = keys.split_last() else {
+        return Err("no keys to set".to_string());
+    };
+    for key in keys {
+        if var.index(key).is_none() {
+            return Err(format!(
+                "failed to index {} with {}",
+                var.to_string()?, key.to_string()?
+            ));
         }
-        // Modify
-        list.push((key, val));
+        var = var.index_mut(key).expect("the key exists");
     }
-    Ok(Value::List(list))
+    return set_key(var, last, val);
 }
 
 // The big switch, runs every instruction
@@ -1010,27 +1236,38 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
 
         // Lists
         Opcode::LL => {
-            let Value::Int(mut size) = vm.pop() else {
+            let View::Int(mut size) = vm.pop().view() else {
                 return Err("Non-int list size".to_string());
             };
-            // Get the keys and values and put them into the list
-            let mut list = Vec::<(String, Value)>::with_capacity(
+            // Get the keys and values
+            let mut items = Vec::<(Value, Value)>::with_capacity(
                 size as usize,
             );
             while size > 0 {
-                let Value::Str(key) = vm.pop() else {
-                    return Err("Non-string list index".to_string());
-                };
+                let key = vm.pop();
                 let val = vm.pop();
-                // Store
-                list.push((key, val));
+                items.push((key, val));
                 size -= 1;
             }
-            list.reverse();
-            vm.push(Value::List(list));
+            // Put them into the list, items without keys have a none key
+            let mut list = KeyedList::default();
+            for (key, val) in items.into_iter().rev() {
+                let key = if let View::None = key.view() {
+                    next_key(&list)
+                } else {
+                    Key::new(&key)?
+                };
+                // `[x, x]` would silently drop an item
+                if list.contains_key(&key) {
+                    let name = key.to_value().to_string()?;
+                    return Err(format!("repeated key {} in list", name));
+                }
+                list.insert(key, val);
+            }
+            vm.push(Value::List(Rc::new(list)));
         },
         Opcode::LFL => {
-            let Value::Int(mut size) = vm.pop() else {
+            let View::Int(mut size) = vm.pop().view() else {
                 return Err("Non-int list size".to_string());
             };
             // Get values
@@ -1040,7 +1277,7 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
                 size -= 1;
             }
             list.reverse();
-            vm.push(Value::FastList(list));
+            vm.push(Value::fast_list(list));
         },
         Opcode::INX => {
             let index = vm.pop();
@@ -1073,25 +1310,53 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
         },
         Opcode::SKY => {
             let key = vm.pop();
-            let list = vm.pop();
+            let mut list = vm.pop();
+            let val = vm.pop();
+            set_key(&mut list, &key, val)?;
+            vm.push(list);
+        },
+        Opcode::SKV => {
+            let Some(varname) = vm.pop().into_rc::<String>() else {
+                return Err("variable name must be string".to_string());
+            };
+            let (keys, val) = pop_keys(vm)?;
+            set_keys(vm.get_var_mut(&varname)?, &keys, val)?;
+        },
+        Opcode::SKLV => {
+            let slot = vm.var_min + vm.read(1) as usize;
+            let (keys, val) = pop_keys(vm)?;
+            let Some(var) = vm.var_vals.get_mut(slot) else {
+                return Err(format!("no local in slot {}", slot));
+            };
+            set_keys(var, &keys, val)?;
+        },
+        Opcode::ALV => {
+            let slot = vm.var_min + vm.read(1) as usize;
             let val = vm.pop();
-            vm.push(set_key(list, key, val)?)
+            let Some(var) = vm.var_vals.get_mut(slot) else {
+                return Err(format!("no local in slot {}", slot));
+            };
+            var.add_assign(val)?;
         },
 
         // Variables
         Opcode::PV => {
             // Get varname
-            let Value::Str(varname) = vm.pop() else {
+            let Some(varname) = vm.pop().into_rc::<String>() else {
                 return Err("variable name must be string".to_string());
             };
-            // Get var
-            let var = vm.get_var(&varname)?;
+            // Get var, functions can be used as values too
+            let var = match vm.get_var(&varname) {
+                Ok(var) => var,
+                Err(msg) => varname.rsplit_once("::")
+                    .and_then(|(_, name)| vm.get_functi(name)).ok_or(msg)?,
+            };
             // Push
             vm.push(var);
         },
         Opcode::SV => {
             // Get varname
-            let Value::Str(varname) = vm.pop() else {
+            let Some(varname) = vm.pop().into_rc::<String>() else {
                 return Err("variable name must be string".to_string());
             };
             // Set
@@ -1099,14 +1364,14 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
             vm.set_var(&varname, val)?;
         },
         Opcode::DV => {
-            let Value::Str(varname) = vm.pop() else {
+            let Some(varname) = vm.pop().into_rc::<String>() else {
                 return Err("variable name must be string".to_string());
             };
             let val = vm.pop();
             vm.make_var(&varname, val)?;
         },
         Opcode::DOS => {
-            let Value::Str(varname) = vm.pop() else {
+            let Some(varname) = vm.pop().into_rc::<String>() else {
                 return Err("variable name must be string".to_string());
             };
             let val = vm.pop();
@@ -1114,6 +1379,30 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
                 vm.set_var(&varname, val)?;
             }
         },
+        Opcode::PLV => {
+            let slot = vm.var_min + vm.read(1) as usize;
+            let Some(val) = vm.var_vals.get(slot) else {
+                return Err(format!("no local in slot {}", slot));
+            };
+            vm.push(val.clone());
+        },
+        Opcode::SLV => {
+            let slot = vm.var_min + vm.read(1) as usize;
+            let val = vm.pop();
+            let Some(var) = vm.var_vals.get_mut(slot) else {
+                return Err(format!("no local in slot {}", slot));
+            };
+            *var = val;
+        },
+        Opcode::DLV => {
+            // The compiler already checked it isn't a redefinition
+            let Some(varname) = vm.pop().into_rc::<String>() else {
+                return Err("variable name must be string".to_string());
+            };
+            let val = vm.pop();
+            vm.var_names.push(Rc::unwrap_or_clone(varname));
+            vm.var_vals.push(val);
+        },
 
         // Binops
         Opcode::ADD => {
@@ -1160,12 +1449,14 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
         Opcode::LT => {
             let rhs = vm.pop();
             let lhs = vm.pop();
-            vm.push(Value::Bool(lhs.to_float() < rhs.to_float()));
+            vm.push(Value::Bool(lhs.compare(&rhs) == Some(Ordering::Less)));
         },
         Opcode::GT => {
             let rhs = vm.pop();
             let lhs = vm.pop();
-            vm.push(Value::Bool(lhs.to_float() > rhs.to_float()));
+            vm.push(Value::Bool(
+                lhs.compare(&rhs) == Some(Ordering::Greater)
+            ));
         },
         Opcode::AND => {
             let rhs = vm.pop();
@@ -1206,22 +1497,37 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
         },
 
         // Functions
-        Opcode::FN => {
-            let Value::Str(name) = vm.pop() else {
-                return Err("Non-string function name".to_string());
-            };
-            let Value::Int(args_num) = vm.pop() else {
+        Opcode::LFN => {
+            let View::Int(arg_num) = vm.pop().view() else {
                 return Err("Non-int arg number".to_string());
             };
-            // Add the function
-            vm.program.functis.insert(name, (vm.at + 5, args_num));
-        }
+            let View::Int(mut capture_num) = vm.pop().view() else {
+                return Err("Non-int capture number".to_string());
+            };
+            // Capture by value, names that aren't locals are left alone
+            let mut captures = vec![];
+            while capture_num > 0 {
+                let Some(name) = vm.pop().into_rc::<String>() else {
+                    return Err("variable name must be string".to_string());
+                };
+                if let Some(index) = vm.get_local_index(&name) {
+                    let val = vm.var_vals[index].clone();
+                    captures.push((Rc::unwrap_or_clone(name), val));
+                }
+                capture_num -= 1;
+            }
+            // The body is after the JMPU
+            vm.push(Value::Functi(Rc::new(FunctiInfo{
+                name: "<anonymous>".to_string(), pos: Some(vm.at + 5),
+                arg_num: arg_num as i32, captures,
+            })));
+        },
 
         Opcode::CALL => {
-            let Value::Str(name) = vm.pop() else {
+            let Some(name) = vm.pop().into_rc::<String>() else {
                 return Err("Non-string function name".to_string());
             };
-            let Value::Int(mut arg_num) = vm.pop() else {
+            let View::Int(mut arg_num) = vm.pop().view() else {
                 return Err("Non-int arg number".to_string());
             };
             // Get the args
@@ -1233,13 +1539,36 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
             // Call
             vm.call(&name, &args)?;
         },
+        Opcode::CALLV => {
+            let functi = vm.pop();
+            let View::Int(mut arg_num) = vm.pop().view() else {
+                return Err("Non-int arg number".to_string());
+            };
+            // Get the args
+            let mut args = Vec::<Value>::with_capacity(arg_num as usize);
+            while arg_num > 0 {
+                args.push(vm.pop());
+                arg_num -= 1;
+            }
+            vm.call_value(&functi, &args)?;
+        },
+        Opcode::CALLD => {
+            let pos = vm.read(3) as usize;
+            let View::Int(mut arg_num) = vm.pop().view() else {
+                return Err("Non-int arg number".to_string());
+            };
+            // Get the args
+            let mut args = Vec::<Value>::with_capacity(arg_num as usize);
+            while arg_num > 0 {
+                args.push(vm.pop());
+                arg_num -= 1;
+            }
+            vm.enter(pos, &args);
+        },
 
         Opcode::TCO => {
             // Clear scope
-            loop {
-                let Some((_, _, c)) = vm.scope.last() else {
-                    break;
-                };
+            while let Some((_, _, c)) = vm.scope.last() {
                 if *c == 0 {
                     // Clean scope
                     vm.raise_scope()?;
@@ -1249,31 +1578,41 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
                 vm.raise_scope()?;
             }
             // Set args
-            let arg_num = vm.call_frames.pop().unwrap().len();
+            let frame = vm.call_frames.last_mut().unwrap();
+            let arg_num = frame.args.len();
             let stack_len = vm.stack.len();
-            vm.call_frames.push(
-                vm.stack[stack_len-arg_num .. stack_len].iter()
-                    .map(|x| x.clone()).rev().collect()
-            );
+            frame.args = vm.stack[stack_len-arg_num .. stack_len].iter()
+                .cloned().rev().collect();
+            frame.tail_calls += 1;
             // Jump
             let offset = vm.read(3);
             vm.jump(-offset);
         },
 
+        // Exceptions
+        Opcode::TRY => {
+            let offset = vm.read(3);
+            let catch = vm.at + offset as usize;
+            vm.handlers.push(Handler{catch, state: vm.save_state()});
+        },
+        Opcode::ENDTRY => {
+            vm.handlers.pop();
+        },
+
         Opcode::RET => {
             let ret = vm.stack.pop().unwrap();
-            while let Value::Iter(..) = vm.stack.last().unwrap() {
+            // Clean up loops
+            while let View::Iter | View::RangeType
+                = vm.stack.last().unwrap().view()
+            {
                 vm.stack.pop();
             }
-            let Value::Int(pos) = vm.stack.pop().unwrap() else {
+            let View::Int(pos) = vm.stack.pop().unwrap().view() else {
                 return Err("Non-int return address".to_string());
             };
             vm.stack.push(ret);
             // Fix scope
-            loop {
-                let Some((_, _, c)) = vm.scope.last() else {
-                    break;
-                };
+            while let Some((_, _, c)) = vm.scope.last() {
                 if *c == 0 {
                     vm.raise_scope()?;
                     break;
@@ -1290,11 +1629,103 @@ fn exec_next(vm: &mut Vm) -> Result<(), String> {
     Ok(())
 }
 
+// Runs one instruction and moves forward, false means it's at the end
+pub fn step(vm: &mut Vm) -> Result<bool, String> {
+    if let Err(msg) = exec_next(vm) {
+        if !vm.catch(&msg) {
+            return Err(msg);
+        }
+    }
+    // Move forward
+    if vm.jump {
+        vm.jump = false;
+        return Ok(true);
+    }
+    if vm.at + 1 == vm.program.ops.len() {
+        return Ok(false);
+    }
+    vm.next_op();
+    return Ok(true);
+}
+
+// Prints one line of a traceback, pointing at the expression of `at`
+fn print_frame(vm: &Vm, at: usize, msg: &str, errtype: ErrType) {
+    let color = vm.args.extensions.contains(&"color".to_string());
+    let (line, file) = vm.program.get_info(at as u32);
+    // Fallback to the whole statement
+    let stream = match vm.program.get_span(at as u32) {
+        Some(span) => Stream{
+            name: file, line: span.line as usize, at: span.at as usize,
+            rat: 0, size: span.size as usize,
+        },
+        None => line_stream(&file, line),
+    };
+    err(&stream, msg, errtype, color);
+}
+
+// Names a frame for tracebacks
+fn frame_name(vm: &Vm, frame: Option<&CallFrame>) -> String {
+    let Some(frame) = frame else {
+        return "<top level>".to_string();
+    };
+    let name = format!("{}()", vm.program.functi_name(frame.pos));
+    return match frame.tail_calls {
+        0 => name,
+        1 => name + ", after 1 tail call",
+        n => format!("{}, after {} tail calls", name, n),
+    };
+}
+
+// The calls at the current op, innermost last, as (name, line, file)
+pub fn get_traceback(vm: &Vm) -> Vec<(String, u32, String)> {
+    let frames = &vm.call_frames;
+    let mut ret = vec![];
+    for (depth, frame) in frames.iter().enumerate() {
+        let caller = if depth == 0 { None } else { frames.get(depth - 1) };
+        let (line, file) = vm.program.get_info(frame.call_site as u32);
+        ret.push((frame_name(vm, caller), line, file));
+    }
+    let (line, file) = vm.program.get_info(vm.at as u32);
+    ret.push((frame_name(vm, frames.last()), line, file));
+    return ret;
+}
+
+// Prints a runtime error with a traceback, innermost frame last
+pub fn report_err(vm: &mut Vm, msg: &str) {
+    println!("Traceback (most recent call last):");
+    let frames = &vm.call_frames;
+    let mut repeats = 0;
+    for (depth, frame) in frames.iter().enumerate() {
+        // Deep recursion repeats the same call over and over
+        if depth > 0 && frames[depth - 1].call_site == frame.call_site {
+            repeats += 1;
+            continue;
+        }
+        if repeats != 0 {
+            println!("  [previous call repeated {} more times]", repeats);
+            repeats = 0;
+        }
+        let caller = if depth == 0 { None } else { frames.get(depth - 1) };
+        print_frame(
+            vm, frame.call_site,
+            &format!("in {}", frame_name(vm, caller)), ErrType::Note
+        );
+    }
+    if repeats != 0 {
+        println!("  [previous call repeated {} more times]", repeats);
+    }
+    print_frame(
+        vm, vm.at,
+        &format!("{} (in {})", msg, frame_name(vm, frames.last())), ErrType::Err
+    );
+}
+
 pub fn run(vm: &mut Vm) -> bool {
     if vm.program.ops.is_empty() {
         return true;
     }
     vm.stack = vec![];
+    vm.use_decimals();
     if vm.args.is_debug {
         println!("Ops: {:?}", vm.program.ops);
     }
@@ -1306,35 +1737,35 @@ pub fn run(vm: &mut Vm) -> bool {
             println!("{}: {:?}({}) {:?}", vm.at, opcode, op, vm.stack);
         }
         // Run
-        if let Err(s) = exec_next(vm) {
-            let (line, filename) = vm.program.get_info(vm.at as u32);
-            println!("Runtime Error in {}:{}: {}", filename, line, s);
-            vm.at = vm.program.ops.len() - 1;
-            return false;
-        }
-
-        // Move forward
-        if vm.jump {
-            vm.jump = false;
-            continue;
-        }
-        if vm.at + 1 != vm.program.ops.len() {
-            vm.next_op();
-        } else {
-            // At the end
-            if vm.args.is_debug {
-                // print the stack at the end
-                println!("FINAL: {:?}", vm.stack);
-            }
-            if vm.args.is_repl && !vm.stack.is_empty() {
-                // Print the result
-                if vm.stack[0] != Value::None {
-                    print!("{}", vm.stack[0].to_string()
-                        .and_then(|x| Ok(x + "\n")).unwrap_or("".to_string())
-                    );
-                }
+        match step(vm) {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(s) => {
+                report_err(vm, &s);
+                vm.at = vm.program.ops.len() - 1;
+                vm.call_frames.clear();
+                vm.handlers.clear();
+                // Back to the top level, slots start from an empty scope
+                vm.scope.clear();
+                vm.var_names.clear();
+                vm.var_vals.clear();
+                vm.var_min = 0;
+                vm.is_global = true;
+                return false;
             }
-            break;
+        }
+    }
+    // At the end
+    if vm.args.is_debug {
+        // print the stack at the end
+        println!("FINAL: {:?}", vm.stack);
+    }
+    if vm.args.is_repl && !vm.stack.is_empty() {
+        // Print the result
+        if vm.stack[0] != Value::None {
+            print!("{}", vm.stack[0].to_string()
+                .map(|x| x + "\n").unwrap_or("".to_string())
+            );
         }
     }
     return true;
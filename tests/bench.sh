@@ -0,0 +1,21 @@
+#!/usr/bin/env bash
+# Times tests/speedtest.sk, printing the best of a few runs
+# Usage: tests/bench.sh [burlap binary] [runs]
+# Without a binary, the release build is built and used
+set -e
+cd "$(dirname "$0")/.."
+
+burlap="$1"
+runs="${2:-5}"
+if [ -z "$burlap" ]; then
+    cargo build --release -q
+    burlap=target/release/burlap
+fi
+
+TIMEFORMAT=%R
+times=""
+for _ in $(seq "$runs"); do
+    times+="$({ time "$burlap" tests/speedtest.sk > /dev/null; } 2>&1)"$'\n'
+done
+best=$(echo -n "$times" | sort -n | head -n 1)
+echo "speedtest.sk: ${best}s (best of $runs)"
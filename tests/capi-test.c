@@ -0,0 +1,147 @@
+// Tests the C API, see the README for how to build it
+#include <stdio.h>
+#include <string.h>
+
+#include "burlap.h"
+
+static int failed = 0;
+
+static void check(bool ok, const char *name) {
+    if (!ok) {
+        printf("%s failed!\n", name);
+        failed++;
+    }
+}
+
+// Adds two numbers, counting calls in `data`
+static bool add(
+    const burlap_value *args, size_t argc, burlap_value *ret, void *data
+) {
+    (void) argc;
+    if (args[0].type != BURLAP_INT || args[1].type != BURLAP_INT) {
+        ret->type = BURLAP_STR;
+        ret->as.str = "add() needs numbers";
+        return false;
+    }
+    (*(int *) data)++;
+    ret->type = BURLAP_INT;
+    ret->as.i = args[0].as.i + args[1].as.i;
+    return true;
+}
+
+// Joins strings
+static bool join(
+    const burlap_value *args, size_t argc, burlap_value *ret, void *data
+) {
+    static char buf[256];
+    (void) data;
+    buf[0] = '\0';
+    for (size_t i = 0; i < argc; i++) {
+        if (args[i].type != BURLAP_STR) {
+            return false;
+        }
+        strncat(buf, args[i].as.str, sizeof(buf) - strlen(buf) - 1);
+    }
+    ret->type = BURLAP_STR;
+    ret->as.str = buf;
+    return true;
+}
+
+int main(void) {
+    burlap *sack = burlap_new();
+    burlap_value val;
+
+    // Eval
+    check(burlap_eval(sack, "1 + 2", &val), "eval");
+    check(val.type == BURLAP_INT && val.as.i == 3, "eval value");
+    check(burlap_eval(sack, "\"hi\" + \"!\"", &val), "eval string");
+    check(
+        val.type == BURLAP_STR && strcmp(val.as.str, "hi!") == 0,
+        "eval string value"
+    );
+    check(burlap_eval(sack, "[1, 2]", &val), "eval list");
+    check(val.type == BURLAP_OTHER, "eval list value");
+    check(burlap_eval(sack, "let x = 2.5;", NULL), "eval NULL");
+    check(burlap_eval_file(sack, "tests/empty.sk", &val), "eval file");
+    check(val.type == BURLAP_NONE, "eval file value");
+    check(!burlap_eval_file(sack, "tests/nope.sk", &val), "eval missing file");
+
+    // Calls
+    check(
+        burlap_eval(sack, "functi scale(n, by) { return n * by; }", NULL),
+        "functi"
+    );
+    burlap_value args[2] = {
+        {.type = BURLAP_INT, .as.i = 4}, {.type = BURLAP_FLOAT, .as.f = 0.5}
+    };
+    check(burlap_call(sack, "scale", args, 2, &val), "call");
+    check(val.type == BURLAP_FLOAT && val.as.f == 2.0, "call value");
+    check(!burlap_call(sack, "nope", NULL, 0, &val), "call missing");
+
+    // Functions can be redefined, calls to them use the new one
+    check(
+        burlap_eval(sack, "functi a() { return 1; } functi g() { return a(); }",
+            NULL),
+        "callee"
+    );
+    check(burlap_eval(sack, "functi a() { return 2; }", &val), "redefine");
+    check(burlap_eval(sack, "g()", &val), "redefine call");
+    check(val.type == BURLAP_INT && val.as.i == 2, "redefine call value");
+    check(burlap_eval(sack, "functi a(x) { return x; }", &val), "redefine args");
+    check(!burlap_eval(sack, "g()", &val), "redefine args call");
+    check(
+        strstr(burlap_last_error(sack), "too few args for a") != NULL,
+        "redefine args message"
+    );
+    // But not twice in the same code
+    check(
+        !burlap_eval(sack, "functi d(x) { return 1; } functi d() {}", &val),
+        "duplicate"
+    );
+    check(burlap_eval(sack, "functi d() { return 3; } d()", &val), "undo");
+    check(val.type == BURLAP_INT && val.as.i == 3, "undo value");
+
+    // Errors
+    check(!burlap_eval(sack, "let = 1;", &val), "syntax error");
+    check(strlen(burlap_last_error(sack)) > 0, "syntax error message");
+    check(!burlap_eval(sack, "[][1]", &val), "runtime error");
+    check(
+        strstr(burlap_last_error(sack), "failed to index") != NULL,
+        "runtime error message"
+    );
+
+    // Callbacks
+    int calls = 0;
+    check(burlap_register(sack, "add", 2, add, &calls), "register");
+    check(burlap_register(sack, "join", -1, join, NULL), "register any");
+    check(burlap_eval(sack, "add(add(1, 2), 3)", &val), "callback");
+    check(
+        val.type == BURLAP_INT && val.as.i == 6 && calls == 2,
+        "callback value"
+    );
+    check(burlap_eval(sack, "join(\"a\", \"b\", \"c\")", &val), "callback any");
+    check(strcmp(val.as.str, "abc") == 0, "callback any value");
+    check(!burlap_eval(sack, "add(1)", &val), "callback args");
+    check(
+        strstr(burlap_last_error(sack), "too few args for add") != NULL,
+        "callback args message"
+    );
+    check(!burlap_eval(sack, "add(1, \"2\")", &val), "callback error");
+    check(
+        strcmp(burlap_last_error(sack), "<eval>:1:0: add() needs numbers") == 0,
+        "callback error message"
+    );
+    check(!burlap_eval(sack, "join(1)", &val), "callback fail");
+    check(
+        strstr(burlap_last_error(sack), "join() failed") != NULL,
+        "callback fail message"
+    );
+
+    burlap_free(sack);
+    if (failed) {
+        printf("%d TESTS FAILED!\n", failed);
+        return 1;
+    }
+    printf("ALL TESTS PASSED!\n");
+    return 0;
+}
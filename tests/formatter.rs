@@ -0,0 +1,77 @@
+// Tests the -f/--format autoformatter
+#![allow(clippy::needless_return)]
+use std::fs;
+use std::process::Command;
+
+// Formats code with `burlap -f - <code>`
+fn format(src: &str) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_burlap"))
+        .args(["--no-color", "-f", "-", src])
+        .output().expect("burlap runs");
+    assert!(out.status.success(), "failed to format:\n{}", src);
+    return String::from_utf8(out.stdout).expect("formatted code is utf-8");
+}
+
+#[test]
+fn indents_blocks() {
+    assert_eq!(
+        format("if true {\nprint(1);\n}"),
+        "if true {\n    print(1);\n}\n"
+    );
+    assert_eq!(
+        format("functi f(a,b){\nreturn a+b;}"),
+        "functi f(a, b) {\n    return a + b;\n}\n"
+    );
+}
+
+#[test]
+fn closing_braces_get_their_own_line() {
+    assert_eq!(format("{\nprint(2);}"), "{\n    print(2);\n}\n");
+    assert_eq!(
+        format("if x { a();\n} else { b(); }"),
+        "if x {\n    a();\n} else { b(); }\n"
+    );
+    assert_eq!(
+        format("loop (i in range(0, 3)) { print(i);\n  print(i); }"),
+        "loop (i in range(0, 3)) {\n    print(i);\n    print(i);\n}\n"
+    );
+    // One line blocks are left alone
+    assert_eq!(format("if x { a(); }"), "if x { a(); }\n");
+}
+
+#[test]
+fn joins_braces_else_and_catch() {
+    assert_eq!(
+        format("if x\n{\n    a();\n}\nelse\n{\n    b();\n}"),
+        "if x {\n    a();\n} else {\n    b();\n}\n"
+    );
+    assert_eq!(
+        format("try {\n    a();\n}\ncatch (e) {\n    b();\n}"),
+        "try {\n    a();\n} catch (e) {\n    b();\n}\n"
+    );
+}
+
+#[test]
+fn keeps_comments() {
+    assert_eq!(
+        format("if x { # why\na(); # what\n}"),
+        "if x { # why\n    a(); # what\n}\n"
+    );
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for entry in fs::read_dir("tests").expect("tests exists") {
+        let path = entry.expect("readable dir").path();
+        if path.extension().is_none_or(|ext| ext != "sk") {
+            continue;
+        }
+        let src = fs::read_to_string(&path).expect("readable file");
+        // `-` can't take empty code
+        if src.is_empty() {
+            continue;
+        }
+        let once = format(&src);
+        assert_eq!(format(&once), once, "{} changed", path.display());
+    }
+}
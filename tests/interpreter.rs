@@ -0,0 +1,200 @@
+// Tests the Rust embedding API
+#![allow(clippy::needless_return)]
+use burlap::{ErrorKind, FromValue, Interpreter, IntoValue, Value, View};
+
+#[test]
+fn eval_globals_come_first() {
+    let mut sack = Interpreter::new();
+    sack.eval("let name = \"eval\";").expect("valid code");
+    // Has its own `name`
+    sack.eval_file("tests/module-test.sk").expect("valid file");
+    assert_eq!(sack.get_global("name"), Some(Value::str("eval")));
+    sack.set_global("name", Value::str("set"));
+    assert_eq!(sack.eval("name").expect("valid code"), Value::str("set"));
+    // Files are still checked when eval doesn't have one
+    assert_eq!(sack.get_global("secret"), Some(Value::Int(42)));
+}
+
+#[test]
+fn nested_compile_errors_fail() {
+    let mut sack = Interpreter::new();
+    sack.eval("functi f(a) { return a; }").expect("valid code");
+    for src in [
+        "let x = nofn(1); x",
+        "if nofn() {}",
+        "if false {} else { nofn(); }",
+        "if true { nofn(); }",
+        "functi g() { if true { nofn(); } }",
+        "loop (i in f(1, 2)) {}",
+        "loop (while nofn()) {}",
+    ] {
+        let err = sack.eval(src).expect_err(src);
+        assert_eq!(err.kind, ErrorKind::Syntax, "{}", src);
+    }
+    // Nothing from the failed code was kept
+    assert!(sack.eval("x").is_err());
+    assert!(sack.call("g", vec![]).is_err());
+}
+
+#[test]
+fn functions_can_be_redefined() {
+    let mut sack = Interpreter::new();
+    sack.eval("functi f() { return 1; } functi g() { return f(); }")
+        .expect("valid code");
+    sack.eval("functi f() { return 2; }").expect("redefined");
+    assert_eq!(sack.eval("g()").expect("valid code"), Value::Int(2));
+    assert_eq!(sack.call("f", vec![]).expect("valid call"), Value::Int(2));
+    // Not in the same code though
+    let err = sack.eval("functi h() {} functi h() {}").expect_err("defined");
+    assert_eq!(err.message, "function \"h\" is already defined");
+}
+
+#[test]
+fn missing_variables_point_at_the_name() {
+    let mut sack = Interpreter::new();
+    let err = sack.eval("let a = 1;\nprint(a + zzz);").expect_err("no zzz");
+    assert_eq!(err.message, "no variable called \"zzz\"");
+    assert_eq!((err.line, err.column), (2, 10));
+    let err = sack.eval("yy = 3;").expect_err("no yy");
+    assert_eq!(err.message, "no variable called \"yy\"");
+    assert_eq!((err.line, err.column), (1, 0));
+}
+
+#[test]
+fn values_round_trip() {
+    assert_eq!(i64::from_value(7i64.into_value()), Ok(7));
+    assert_eq!(i32::from_value((-7i32).into_value()), Ok(-7));
+    assert_eq!(f64::from_value(1.5f64.into_value()), Ok(1.5));
+    assert_eq!(f32::from_value(0.25f32.into_value()), Ok(0.25));
+    assert_eq!(String::from_value("hi".into_value()), Ok("hi".to_string()));
+    let list = vec![1i64, 2, 3];
+    assert_eq!(Vec::<i64>::from_value(list.clone().into_value()), Ok(list));
+    assert_eq!(Option::<i64>::from_value(Some(3i64).into_value()), Ok(Some(3)));
+    assert_eq!(Option::<i64>::from_value(None::<i64>.into_value()), Ok(None));
+    // Through sack
+    let mut sack = Interpreter::new();
+    sack.register_fn("int", |x: i64| x);
+    sack.register_fn("decimal", |x: f64| x);
+    sack.register_fn("text", |x: String| x);
+    sack.register_fn("echo", |x: Option<Vec<i32>>| x);
+    assert_eq!(sack.eval("int(-3)").expect("valid code"), Value::Int(-3));
+    assert_eq!(
+        sack.eval("decimal(0.5)").expect("valid code"), Value::Float(0.5)
+    );
+    assert_eq!(
+        sack.eval("text(\"hi\")").expect("valid code"), Value::str("hi")
+    );
+    assert_eq!(
+        sack.eval("echo([1, 2])").expect("valid code"),
+        Value::fast_list(vec![Value::Int(1), Value::Int(2)])
+    );
+    assert_eq!(sack.eval("echo(none)").expect("valid code"), Value::None);
+    // Numbers can be decimals
+    assert_eq!(f64::from_value(Value::Int(2)), Ok(2.0));
+}
+
+#[test]
+fn boxed_values() {
+    // Ints around where they stop fitting in the box
+    for i in [
+        0, -1, (1 << 47) - 1, 1 << 47, -(1 << 47), -(1 << 47) - 1,
+        i64::MAX, i64::MIN,
+    ] {
+        assert_eq!(Value::Int(i).view(), View::Int(i));
+        assert_eq!(Value::Int(i), Value::Int(i).clone());
+    }
+    for f in [0.0, -0.0, 1.5, -2.5e300, f64::INFINITY, f64::NEG_INFINITY] {
+        assert_eq!(Value::Float(f).view(), View::Float(f));
+    }
+    let View::Float(nan) = Value::Float(-f64::NAN).view() else {
+        panic!("NaN isn't a decimal");
+    };
+    assert!(nan.is_nan());
+    assert_eq!(Value::Bool(true).view(), View::Bool(true));
+    assert_eq!(Value::Byte(255).view(), View::Byte(255));
+    assert_eq!(Value::None.view(), View::None);
+    // Big ints are only big when they don't fit in an i64
+    let mut sack = Interpreter::new();
+    let big = sack.eval("9223372036854775807 + 1").expect("valid code");
+    assert!(matches!(big.view(), View::BigInt(_)));
+    assert_eq!(big.to_string(), Ok("9223372036854775808".to_string()));
+    let back = sack.eval("9223372036854775808 - 1").expect("valid code");
+    assert_eq!(back.view(), View::Int(i64::MAX));
+    // Copies of lists don't change with the original
+    let list = sack.eval(
+        "functi f() { let a = [1, 2]; let b = a; b += 3; b[0] = 5; return a; }
+        f()"
+    ).expect("valid code");
+    assert_eq!(list, Value::fast_list(vec![Value::Int(1), Value::Int(2)]));
+}
+
+#[test]
+fn bad_native_args() {
+    let mut sack = Interpreter::new();
+    sack.register_fn("twice", |l: Vec<i32>| {
+        l.into_iter().map(|x| x * 2).collect::<Vec<i32>>()
+    });
+    let err = |sack: &mut Interpreter, src| {
+        return sack.eval(src).expect_err(src).message;
+    };
+    assert_eq!(
+        err(&mut sack, "twice(1)"), "twice() argument 1 must be a list"
+    );
+    assert_eq!(
+        err(&mut sack, "twice([1, \"a\"])"),
+        "twice() argument 1 element 1 must be a number"
+    );
+    assert_eq!(
+        err(&mut sack, "twice([9999999999])"),
+        "twice() argument 1 element 0 must fit in 32 bits"
+    );
+    assert_eq!(
+        err(&mut sack, "twice()"), "too few args for twice (got 0 need 1)"
+    );
+    assert_eq!(
+        err(&mut sack, "twice([], [])"),
+        "too many args for twice (got 2 need 1)"
+    );
+    assert_eq!(
+        sack.eval("twice([1, 2])").expect("valid code"),
+        Value::fast_list(vec![Value::Int(2), Value::Int(4)])
+    );
+}
+
+#[test]
+fn eval_and_call() {
+    let mut sack = Interpreter::new();
+    assert_eq!(sack.eval("1 + 2").expect("valid code"), Value::Int(3));
+    assert_eq!(sack.eval("let x = 1;").expect("valid code"), Value::None);
+    sack.eval("functi add(a, b) {\n    return a + b;\n}").expect("valid code");
+    assert_eq!(
+        sack.call("add", vec![Value::Int(2), Value::Int(3)]).expect("valid"),
+        Value::Int(5)
+    );
+    assert_eq!(
+        sack.call("type", vec![Value::Int(4)]).expect("valid"),
+        Value::str("Number")
+    );
+    // Bad calls point at the definition
+    let err = sack.call("add", vec![]).expect_err("too few args");
+    assert_eq!(err.message, "too few args for add (got 0 need 2)");
+    assert_eq!((err.file.as_str(), err.line), ("<eval>", 1));
+    let err = sack.call("nope", vec![]).expect_err("no function");
+    assert_eq!(err.line, 0);
+}
+
+#[test]
+fn error_kinds() {
+    let mut sack = Interpreter::new();
+    let err = sack.eval("let = 1;").expect_err("bad syntax");
+    assert_eq!(err.kind, ErrorKind::Syntax);
+    assert_eq!((err.file.as_str(), err.line), ("<eval>", 1));
+    let err = sack.eval("\n[][1]").expect_err("bad index");
+    assert_eq!(err.kind, ErrorKind::Runtime);
+    assert_eq!(err.line, 2);
+    assert_eq!(err.traceback.len(), 1);
+    let err = sack.eval_file("tests/missing.sk").expect_err("no file");
+    assert_eq!(err.kind, ErrorKind::Io);
+    // Errors don't break later code
+    assert_eq!(sack.eval("2").expect("valid code"), Value::Int(2));
+}
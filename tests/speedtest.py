@@ -5,3 +5,10 @@ def fib(n):
     return fib(n - 1) + fib(n - 2)
 
 print("Fib 30:", fib(30));
+
+# Lists and strings
+words = ["sack", "burlap", "bag", "pouch"]
+letters = 0
+for i in range(300000):
+    letters += len(words[i % 4]) - 1
+print("Letters:", letters)